@@ -0,0 +1,131 @@
+use crate::bitboard::{BitBoard, EMPTY};
+use crate::board::Board;
+use crate::color::Color;
+use crate::square::{Square, NUM_SQUARES};
+use crate::tables::*;
+
+/// Squares threatened by one color, split up by the kind of attack.
+#[derive(Clone)]
+pub struct AttackMap {
+    /// Squares a soldier could capture on by moving forward or sideways
+    pub soldiers: BitBoard,
+    /// Squares a cannon could shoot
+    pub cannons: BitBoard,
+    /// For every square, the soldiers capturing on it and the cannon heads shooting it
+    pub attackers: [BitBoard; NUM_SQUARES],
+}
+
+impl AttackMap {
+    pub fn new(board: &Board, color: Color) -> Self {
+        let mut map = Self {
+            soldiers: EMPTY,
+            cannons: EMPTY,
+            attackers: [EMPTY; NUM_SQUARES],
+        };
+        let my_pieces = board.pieces_with_color(color);
+        let unoccupied = !board.pieces_with_castles();
+
+        for src in my_pieces {
+            let src_bb = BitBoard::from_square(src);
+            let captures = front(color, src) | sides(color, src);
+            map.soldiers |= captures;
+            for dst in captures {
+                map.attackers[dst.to_index()] |= src_bb;
+            }
+
+            for d in 0..8 {
+                let body = shot_body(src, d);
+                if (shot_blocker(src, d) & unoccupied).is_not_empty()
+                    && body.is_not_empty()
+                    && (body & my_pieces) == body
+                {
+                    let targets = shot_targets(src, d);
+                    map.cannons |= targets;
+                    for dst in targets {
+                        map.attackers[dst.to_index()] |= src_bb;
+                    }
+                }
+            }
+        }
+        map
+    }
+
+    pub fn all(&self) -> BitBoard {
+        self.soldiers | self.cannons
+    }
+
+    pub fn attackers_of(&self, square: Square) -> BitBoard {
+        self.attackers[square.to_index()]
+    }
+
+    pub fn is_attacked(&self, square: Square) -> bool {
+        (self.all() & BitBoard::from_square(square)).is_not_empty()
+    }
+}
+
+#[test]
+fn attacks_test() {
+    let board = Board::start_position();
+    assert!(!board.town_threatened(Color::White));
+    assert!(!board.town_threatened(Color::Black));
+    // every soldier attacks the squares in front of it and its neighbours
+    assert!((board.attacks(Color::White) & BitBoard::from_square(Square::D4)).is_not_empty());
+
+    let mut board = Board::new(Color::White);
+    board.set_castle(Color::White, Square::H1);
+    board.set_castle(Color::Black, Square::A8);
+    board.set(Color::White, Square::A3);
+    board.set(Color::White, Square::A4);
+    board.set(Color::White, Square::A5);
+    board.set(Color::Black, Square::B4);
+
+    let map = board.attack_map(Color::White);
+    assert!(map.is_attacked(Square::A8));
+    assert_eq!(
+        map.attackers_of(Square::A8),
+        BitBoard::from_square(Square::A5)
+    );
+    assert_eq!(
+        map.attackers_of(Square::B4),
+        BitBoard::from_square(Square::A3) | BitBoard::from_square(Square::A4)
+    );
+    assert!(board.town_threatened(Color::Black));
+
+    // a blocked cannon does not threaten the town anymore
+    board.set(Color::Black, Square::A6);
+    assert!(!board.town_threatened(Color::Black));
+}
+
+#[test]
+fn town_threat_test() {
+    use crate::color::ALL_COLORS;
+    use crate::rng::Rng;
+
+    // the direct check agrees with the attack map in random games, shots included
+    let mut rng = Rng::new(3);
+    let mut shots = 0;
+    for _ in 0..50 {
+        let mut board = Board::start_position();
+        for _ in 0..80 {
+            for color in ALL_COLORS {
+                let map = AttackMap::new(&board, !color);
+                let town = board.castle_with_color(color);
+                assert_eq!(
+                    board.town_threatened(color),
+                    (map.all() & town).is_not_empty()
+                );
+                shots += (map.cannons & town).is_not_empty() as usize;
+            }
+            let moves = board.generate_moves();
+            if moves.is_empty() {
+                break;
+            }
+            let m = moves.get(rng.below(moves.len()));
+            if board.captures_town(m) {
+                break;
+            }
+            board.apply_move(m);
+        }
+    }
+    assert!(shots > 0);
+}
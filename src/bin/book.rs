@@ -0,0 +1,87 @@
+use cannon::book::{selfplay_records, Book};
+use cannon::engine_match::MatchConfig;
+use cannon::player::Player;
+use cannon::record::{read_records, GameRecord};
+use cannon::search::{SearchLimits, Searcher};
+use std::process::exit;
+
+const USAGE: &str = "Usage: book <output> <records>... [--plies N] [--min-games N]
+       book <output> --selfplay <games> [--depth N] [--random-plies N] [--concurrency N] [--plies N] [--min-games N]";
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let usage = || -> ! {
+        println!("{}", USAGE);
+        exit(1);
+    };
+    if args.len() < 3 {
+        usage();
+    }
+    let number = |value: Option<&String>| -> usize {
+        value
+            .and_then(|v| v.parse().ok())
+            .unwrap_or_else(|| usage())
+    };
+
+    let mut paths = Vec::new();
+    let mut selfplay = None;
+    let mut depth = 8;
+    let mut random_plies = 2;
+    let mut concurrency = 1;
+    let mut plies = 12;
+    let mut min_games = 2;
+    let mut i = 2;
+    while i < args.len() {
+        if !args[i].starts_with("--") {
+            paths.push(args[i].clone());
+            i += 1;
+            continue;
+        }
+        let value = number(args.get(i + 1));
+        match args[i].as_str() {
+            "--selfplay" => selfplay = Some(value),
+            "--depth" => depth = value as u8,
+            "--random-plies" => random_plies = value,
+            "--concurrency" => concurrency = value,
+            "--plies" => plies = value,
+            "--min-games" => min_games = value as u32,
+            _ => usage(),
+        }
+        i += 2;
+    }
+
+    let mut records: Vec<GameRecord> = Vec::new();
+    for path in paths.iter() {
+        match read_records(path) {
+            Ok(r) => records.extend(r),
+            Err(e) => {
+                println!("Could not read game records from {}: {}", path, e);
+                exit(1);
+            }
+        }
+    }
+    if let Some(games) = selfplay {
+        // short random openings keep the searcher from playing the same game every time
+        let config = MatchConfig {
+            limits: SearchLimits::depth(depth),
+            games,
+            concurrency,
+            ..MatchConfig::default()
+        };
+        let searcher = || -> Box<dyn Player> {
+            let mut searcher = Searcher::with_tt_size(1 << 20);
+            searcher.verbose = false;
+            Box::new(searcher)
+        };
+        records.extend(selfplay_records(searcher, random_plies, &config, |game| {
+            println!("game {} {}", game.index + 1, game.record.result);
+        }));
+    }
+
+    let book = Book::from_records(&records, plies, min_games);
+    println!("{} positions from {} games", book.len(), records.len());
+    if let Err(e) = book.write_file(&args[1]) {
+        println!("Could not write book to {}: {}", args[1], e);
+        exit(1);
+    }
+}
@@ -0,0 +1,19 @@
+use cannon::protocol::Engine;
+use std::io;
+
+const USAGE: &str = "Usage: cli [--stats]";
+
+fn main() -> io::Result<()> {
+    let mut engine = Engine::new();
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            // search statistics after every search, for tuning the pruning
+            "--stats" => engine.stats = true,
+            _ => {
+                println!("{}", USAGE);
+                std::process::exit(1);
+            }
+        }
+    }
+    engine.run(io::stdin().lock(), io::stdout())
+}
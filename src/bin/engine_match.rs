@@ -0,0 +1,105 @@
+use cannon::color::Color;
+use cannon::engine_match::{run_match, MatchArgs, MatchConfig, Sprt};
+use cannon::player::from_spec;
+use cannon::record::append_records;
+use std::process::exit;
+
+const USAGE: &str =
+    "Usage: engine_match <engine> <engine> <output records> [--games N] [--concurrency N] \
+[--depth N | --nodes N | --movetime MS] [--openings file] [--sprt elo0 elo1]
+
+Engines are random[:seed], greedy, alphabeta[:params], mcts[:params] or cmd:<program>";
+
+fn usage() -> ! {
+    println!("{}", USAGE);
+    exit(1);
+}
+
+fn fail(message: &str) -> ! {
+    println!("{}", message);
+    exit(1);
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() < 4 {
+        usage();
+    }
+    let (first, second, output) = (&args[1], &args[2], &args[3]);
+    // fail before starting any threads if an engine can not be created
+    for spec in [first, second] {
+        if let Err(e) = from_spec(spec) {
+            fail(&format!("Could not create engine {}: {}", spec, e));
+        }
+    }
+
+    let mut config = MatchConfig::default();
+    let mut match_args = MatchArgs::new(config.games, config.concurrency, config.limits);
+    let mut i = 4;
+    while i < args.len() {
+        match match_args.parse(&args[i..]) {
+            Ok(0) => {}
+            Ok(taken) => {
+                i += taken;
+                continue;
+            }
+            Err(e) => {
+                println!("{}", e);
+                usage();
+            }
+        }
+        match args[i].as_str() {
+            "--sprt" => {
+                let elo = |offset: usize| {
+                    args.get(i + offset)
+                        .and_then(|v| v.parse().ok())
+                        .unwrap_or_else(|| usage())
+                };
+                config.sprt = Some(Sprt::new(elo(1), elo(2)));
+                i += 3;
+            }
+            _ => usage(),
+        }
+    }
+    config.games = match_args.games;
+    config.concurrency = match_args.concurrency;
+    config.limits = match_args.limits;
+    let openings = match_args.openings().unwrap_or_else(|e| fail(&e));
+
+    let (score, status) = run_match(
+        || from_spec(first).unwrap(),
+        || from_spec(second).unwrap(),
+        &openings,
+        &config,
+        |game, score| {
+            let record = match game.first_color {
+                Color::White => game.record.clone().with_players(first, second),
+                Color::Black => game.record.clone().with_players(second, first),
+            };
+            if let Err(e) = append_records(output, &[record]) {
+                println!("Could not write game record to {}: {}", output, e);
+            }
+            println!(
+                "Game {} ({} as {}): {}, {}",
+                game.index + 1,
+                first,
+                game.first_color,
+                game.record.result,
+                score
+            );
+        },
+    );
+    println!("{} vs {}: {}", first, second, score);
+    if let Some(sprt) = config.sprt {
+        let (lower, upper) = sprt.bounds();
+        println!(
+            "SPRT [{}, {}]: LLR {:.2} ({:.2}, {:.2}), {:?}",
+            sprt.elo0,
+            sprt.elo1,
+            sprt.llr(&score),
+            lower,
+            upper,
+            status
+        );
+    }
+}
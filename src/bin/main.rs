@@ -1,13 +1,18 @@
 use cannon::board::*;
+use cannon::book::Book;
 use cannon::cannon_move::BitMove;
-use cannon::cannon_move::MoveWithScore;
 use cannon::color::Color::Black;
 use cannon::color::Color::White;
 use cannon::defs::*;
-use cannon::search::Searcher;
+use cannon::eval::eval_trace_with;
+use cannon::player::{GreedyPlayer, Player, RandomPlayer};
+use cannon::rng::Rng;
+use cannon::search::{SearchLimits, Searcher};
+use cannon::skill::{SkillPlayer, ALL_SKILL_LEVELS};
 use cannon::square::Square;
-use cannon::tables::init;
 use macroquad::prelude::*;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
 use std::time::Instant;
 use vek::Vec2;
 
@@ -15,11 +20,42 @@ const SQUARES: u8 = 8;
 
 #[macroquad::main("Cannon")]
 async fn main() {
-    init();
     let mut board = Board::start_position();
     let mut searcher = Searcher::new();
+    // an optional parameter file overrides the default search and eval parameters
+    if let Some(path) = std::env::args().nth(1) {
+        if let Err(e) = searcher.load_params(&path) {
+            println!("Could not load parameters from {}: {}", path, e);
+        }
+    }
+    // an optional opening book, B switches between using and ignoring it
+    let book = std::env::args().nth(2).and_then(|path| {
+        Book::from_file(&path)
+            .map_err(|e| println!("Could not load book from {}: {}", path, e))
+            .ok()
+    });
+    let mut use_book = book.is_some();
+    let mut book_rng = Rng::new(0);
+    let eval_params = searcher.eval_params.clone();
+    // P switches between the opponents
+    let players: Vec<Arc<Mutex<dyn Player + Send>>> = vec![
+        Arc::new(Mutex::new(searcher)),
+        Arc::new(Mutex::new(GreedyPlayer::new())),
+        Arc::new(Mutex::new(RandomPlayer::new(0))),
+    ];
+    let mut current_player = 0;
+    // L cycles through the skill levels, which replace the depth set with J and K
+    let skill_players: Vec<Arc<Mutex<SkillPlayer>>> = ALL_SKILL_LEVELS
+        .into_iter()
+        .map(|level| Arc::new(Mutex::new(SkillPlayer::new(level, 0))))
+        .collect();
+    let mut skill: Option<usize> = None;
+    // the engine searches on its own thread so the window keeps drawing,
+    // the board can not be changed until its move arrives
+    let mut search: Option<JoinHandle<(BitMove, Instant)>> = None;
     let mut last_clicked: Option<Vec2<i32>> = None;
     let mut show_moves = false;
+    let mut show_trace = false;
     let mut won: Option<cannon::color::Color> = None;
     let mut diff = 8;
     let wcsq = board.castle_with_color(White).to_square();
@@ -35,19 +71,66 @@ async fn main() {
         clear_background(BLACK);
 
         if is_key_pressed(KeyCode::T) {
-            show_moves = show_moves ^ true;
+            show_moves ^= true;
+        }
+
+        if is_key_pressed(KeyCode::E) {
+            show_trace ^= true;
+        }
+
+        let thinking = search.is_some();
+
+        if !thinking && is_key_pressed(KeyCode::P) {
+            current_player = (current_player + 1) % players.len();
+            println!(
+                "Playing against {}",
+                players[current_player].lock().unwrap().name()
+            );
+        }
+
+        if !thinking && is_key_pressed(KeyCode::L) {
+            skill = match skill {
+                None => Some(0),
+                Some(i) if i + 1 < skill_players.len() => Some(i + 1),
+                Some(_) => None,
+            };
+            match skill {
+                Some(i) => println!("Skill level {}", skill_players[i].lock().unwrap().level()),
+                None => println!("Skill levels off, depth {}", diff),
+            }
+        }
+
+        if is_key_pressed(KeyCode::B) && book.is_some() {
+            use_book ^= true;
+            println!("Opening book {}", if use_book { "on" } else { "off" });
         }
 
-        if is_key_pressed(KeyCode::G) {
+        if !thinking && is_key_pressed(KeyCode::G) {
             let time = Instant::now();
-            let MoveWithScore { bit_move: m, score } =
-                searcher.search(&mut board.shallow_clone(), diff);
+            let book_move = book
+                .as_ref()
+                .filter(|_| use_book)
+                .and_then(|book| book.choose(&board, &mut book_rng));
+            let limits = SearchLimits::depth(diff);
+            let player: Arc<Mutex<dyn Player + Send>> = match skill {
+                Some(i) => skill_players[i].clone(),
+                None => players[current_player].clone(),
+            };
+            let position = board.shallow_clone();
+            search = Some(std::thread::spawn(move || {
+                let m = book_move
+                    .unwrap_or_else(|| player.lock().unwrap().choose_move(&position, &limits));
+                (m, time)
+            }));
+        }
+
+        if search.as_ref().is_some_and(|s| s.is_finished()) {
+            let (m, time) = search.take().unwrap().join().unwrap();
             println!("{}, {}, {}", m.src(), m.dst(), board.side_to_move());
-            println!("{}", score);
             println!("{}", time.elapsed().as_secs_f32());
             if m == BitMove::null() {
                 won = Some(!board.side_to_move());
-            } else if m.dst() == board.enemy_castle().to_square() {
+            } else if board.captures_town(m) {
                 won = Some(board.side_to_move());
             } else {
                 println!("{}", board.hash());
@@ -58,7 +141,7 @@ async fn main() {
             last_clicked = None;
         }
 
-        if is_key_pressed(KeyCode::U) {
+        if !thinking && is_key_pressed(KeyCode::U) {
             board.undo_move();
             last_clicked = None;
         }
@@ -109,16 +192,16 @@ async fn main() {
 
         for wsq in board.pieces_with_color(White) {
             draw_circle(
-                offset_x + sq_size * (wsq.file_index() as f32 + 0.5) as f32,
-                offset_y + sq_size * ((SQUARES - wsq.rank_index() - 1) as f32 + 0.5) as f32,
+                offset_x + sq_size * (wsq.file_index() as f32 + 0.5),
+                offset_y + sq_size * ((SQUARES - wsq.rank_index() - 1) as f32 + 0.5),
                 sq_size / 2.1,
                 piece_color_map(White),
             );
         }
         for bsq in board.pieces_with_color(Black) {
             draw_circle(
-                offset_x + sq_size * (bsq.file_index() as f32 + 0.5) as f32,
-                offset_y + sq_size * ((SQUARES - bsq.rank_index() - 1) as f32 + 0.5) as f32,
+                offset_x + sq_size * (bsq.file_index() as f32 + 0.5),
+                offset_y + sq_size * ((SQUARES - bsq.rank_index() - 1) as f32 + 0.5),
                 sq_size / 2.5,
                 piece_color_map(Black),
             );
@@ -142,15 +225,15 @@ async fn main() {
         }
         */
         draw_rectangle(
-            offset_x + sq_size * (bc.x as f32 + 0.5 * 0.25) as f32,
-            offset_y + sq_size * ((SQUARES as i32 - bc.y - 1) as f32 + 0.55 * 0.25) as f32,
+            offset_x + sq_size * (bc.x as f32 + 0.5 * 0.25),
+            offset_y + sq_size * ((SQUARES as i32 - bc.y - 1) as f32 + 0.55 * 0.25),
             sq_size / 1.25,
             sq_size / 1.25,
             piece_color_map(Black),
         );
         draw_rectangle(
-            offset_x - 0.5 + sq_size * (wc.x as f32 + 0.5 * 0.25) as f32,
-            offset_y - 0.5 + sq_size * ((SQUARES as i32 - wc.y - 1) as f32 + 0.5 * 0.25) as f32,
+            offset_x - 0.5 + sq_size * (wc.x as f32 + 0.5 * 0.25),
+            offset_y - 0.5 + sq_size * ((SQUARES as i32 - wc.y - 1) as f32 + 0.5 * 0.25),
             sq_size / 1.25,
             sq_size / 1.25,
             piece_color_map(White),
@@ -186,7 +269,18 @@ async fn main() {
                 RED,
             );
         }
-        if is_mouse_button_pressed(MouseButton::Left) {
+        if show_trace {
+            let trace = eval_trace_with(&board, &eval_params);
+            for (i, line) in trace.to_string().lines().enumerate() {
+                draw_text(line, 10., 20. + 18. * i as f32, 18., WHITE);
+            }
+        }
+
+        if thinking {
+            draw_text("Thinking", 10., screen_height() - 10., 18., WHITE);
+        }
+
+        if !thinking && is_mouse_button_pressed(MouseButton::Left) {
             let (x, y) = mouse_position();
             let sqx = ((x - offset_x) / sq_size).floor() as i32;
             let sqy = ((y - offset_y) / sq_size).floor() as i32;
@@ -207,7 +301,7 @@ async fn main() {
                         .find(|m| m.dst() == clicked_sq)
                     {
                         println!("{} -> {}", bitmove.src(), bitmove.dst());
-                        if bitmove.dst() == board.enemy_castle().to_square() {
+                        if board.captures_town(*bitmove) {
                             won = Some(board.side_to_move());
                         }
                         println!("{}", board.hash());
@@ -0,0 +1,64 @@
+use cannon::search::SearchLimits;
+use cannon::selfplay::{generate, SelfPlayConfig};
+use std::process::exit;
+
+const USAGE: &str = "Usage: selfplay <output> <positions> [--depth N | --nodes N] [--threads N] [--seed N] [--random-plies N]";
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() < 3 {
+        println!("{}", USAGE);
+        exit(1);
+    }
+    let number = |value: Option<&String>| -> u64 {
+        value.and_then(|v| v.parse().ok()).unwrap_or_else(|| {
+            println!("{}", USAGE);
+            exit(1);
+        })
+    };
+
+    let positions = number(args.get(2)) as usize;
+    let mut config = SelfPlayConfig::default();
+    let mut threads = 0;
+    let mut seed = 0;
+    let mut i = 3;
+    while i < args.len() {
+        let value = number(args.get(i + 1));
+        match args[i].as_str() {
+            "--depth" => config.limits = SearchLimits::depth(value as u8),
+            "--nodes" => config.limits = SearchLimits::nodes(value),
+            "--threads" => threads = value as usize,
+            "--seed" => seed = value,
+            "--random-plies" => config.random_plies = value as usize,
+            _ => {
+                println!("{}", USAGE);
+                exit(1);
+            }
+        }
+        i += 2;
+    }
+
+    // 0 lets rayon use every core
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build_global()
+        .unwrap();
+
+    let output = &args[1];
+    let result = generate(output, positions, &config, seed, |count| {
+        println!("{} positions", count);
+    });
+    match result {
+        Ok(count) if count < positions => {
+            println!(
+                "Stopped at {} positions, the games no longer reach new ones",
+                count
+            )
+        }
+        Ok(_) => {}
+        Err(e) => {
+            println!("Could not write training data to {}: {}", output, e);
+            exit(1);
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use cannon::square::Square;
+use cannon::tablebase::Tablebase;
+use std::process::exit;
+use std::time::Instant;
+
+const USAGE: &str =
+    "Usage: tablebase <output dir> <max soldiers per color> [--towns fixed|all] [--threads N]";
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let usage = || -> ! {
+        println!("{}", USAGE);
+        exit(1);
+    };
+    if args.len() < 3 {
+        usage();
+    }
+    let max_soldiers: u8 = args[2].parse().unwrap_or_else(|_| usage());
+
+    // the towns of the start position, or every pair on the home ranks
+    let mut towns = vec![(Square::H1, Square::A8)];
+    let mut threads = 0;
+    let mut i = 3;
+    while i < args.len() {
+        match (args[i].as_str(), args.get(i + 1).map(String::as_str)) {
+            ("--towns", Some("fixed")) => {}
+            ("--towns", Some("all")) => towns = Tablebase::all_towns(),
+            ("--threads", Some(n)) => threads = n.parse().unwrap_or_else(|_| usage()),
+            _ => usage(),
+        }
+        i += 2;
+    }
+
+    // 0 lets rayon use every core
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build_global()
+        .unwrap();
+
+    let start = Instant::now();
+    let tablebase = Tablebase::generate(max_soldiers, &towns, |key| {
+        println!(
+            "{} solved after {:.1}s",
+            key.file_name(),
+            start.elapsed().as_secs_f64()
+        );
+    });
+    let output = &args[1];
+    if let Err(e) = tablebase.write_dir(output) {
+        println!("Could not write tablebase to {}: {}", output, e);
+        exit(1);
+    }
+}
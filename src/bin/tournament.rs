@@ -0,0 +1,94 @@
+use cannon::engine_match::MatchArgs;
+use cannon::player::from_spec;
+use cannon::record::read_records;
+use cannon::tournament::{Schedule, Tournament, TournamentConfig};
+use std::process::exit;
+
+const USAGE: &str =
+    "Usage: tournament <output records> <engine>... [--gauntlet] [--games N per pairing] \
+[--concurrency N] [--depth N | --nodes N | --movetime MS] [--openings file]
+
+Engines are random[:seed], greedy, alphabeta[:params], mcts[:params] or cmd:<program>.
+Running again with the same output continues an interrupted tournament.";
+
+fn usage() -> ! {
+    println!("{}", USAGE);
+    exit(1);
+}
+
+fn fail(message: &str) -> ! {
+    println!("{}", message);
+    exit(1);
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() < 4 {
+        usage();
+    }
+    let output = &args[1];
+    let mut engines = Vec::new();
+    let mut config = TournamentConfig::default();
+    let mut match_args = MatchArgs::new(config.games_per_pair, config.concurrency, config.limits);
+    let mut i = 2;
+    while i < args.len() {
+        match match_args.parse(&args[i..]) {
+            Ok(0) => {}
+            Ok(taken) => {
+                i += taken;
+                continue;
+            }
+            Err(e) => {
+                println!("{}", e);
+                usage();
+            }
+        }
+        match args[i].as_str() {
+            "--gauntlet" => config.schedule = Schedule::Gauntlet,
+            flag if flag.starts_with("--") => usage(),
+            engine => engines.push(engine.to_string()),
+        }
+        i += 1;
+    }
+    if engines.len() < 2 {
+        usage();
+    }
+    // engine names are stored in the game records, which separate fields with `;`
+    for spec in engines.iter() {
+        if spec.contains(';') {
+            fail(&format!("Engine {} must not contain ;", spec));
+        }
+        if let Err(e) = from_spec(spec) {
+            fail(&format!("Could not create engine {}: {}", spec, e));
+        }
+    }
+    config.games_per_pair = match_args.games;
+    config.concurrency = match_args.concurrency;
+    config.limits = match_args.limits;
+
+    let tournament = Tournament {
+        engines,
+        openings: match_args.openings().unwrap_or_else(|e| fail(&e)),
+        config,
+    };
+    let total = tournament.schedule().len();
+    let mut played = match read_records(output) {
+        Ok(records) => total - tournament.pending(&records).len(),
+        Err(_) => 0,
+    };
+    let result = tournament.run(output, |record, _| {
+        played += 1;
+        let (white, black) = record.players.clone().unwrap_or_default();
+        println!(
+            "[{}/{}] {} - {}: {}",
+            played, total, white, black, record.result
+        );
+    });
+    match result {
+        Ok(table) => println!("{}", table),
+        Err(e) => fail(&format!(
+            "Could not write game records to {}: {}",
+            output, e
+        )),
+    }
+}
@@ -0,0 +1,48 @@
+use cannon::eval::EvalParams;
+use cannon::params::Params;
+use cannon::record::read_records;
+use cannon::tuner::{extract_positions, Tuner};
+use std::process::exit;
+
+const SKIP_PLIES: usize = 8;
+const MAX_ITERATIONS: usize = 1000;
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() < 3 {
+        println!("Usage: tune <game records> <output params> [start params]");
+        exit(1);
+    }
+
+    let records = read_records(&args[1]).unwrap_or_else(|e| {
+        println!("Could not read game records from {}: {}", args[1], e);
+        exit(1);
+    });
+    let start = match args.get(3) {
+        Some(path) => EvalParams::from_file(path).unwrap_or_else(|e| {
+            println!("Could not load parameters from {}: {}", path, e);
+            exit(1);
+        }),
+        None => EvalParams::default(),
+    };
+
+    let positions = extract_positions(&records, SKIP_PLIES);
+    println!(
+        "{} quiet positions from {} games",
+        positions.len(),
+        records.len()
+    );
+    let mut tuner = Tuner::new(positions);
+    let k = tuner.fit_k(&start);
+    println!("K {}, error {}", k, tuner.error(&start));
+
+    let output = &args[2];
+    let tuned = tuner.tune(&start, MAX_ITERATIONS, |iteration, error, params| {
+        println!("Iteration {}, error {}", iteration, error);
+        // save after every iteration so an interrupted run is not lost
+        if let Err(e) = params.write_file(output) {
+            println!("Could not write parameters to {}: {}", output, e);
+        }
+    });
+    print!("{}", tuned);
+}
@@ -1,9 +1,14 @@
+use crate::attacks::AttackMap;
 use crate::bitboard::BitBoard;
 use crate::bitboard::EMPTY;
-use crate::cannon_move::BitMove;
-use crate::color::{Color, NUM_COLORS};
+use crate::cannon_move::{BitMove, MoveKind};
+use crate::color::{Color, ALL_COLORS, NUM_COLORS};
+use crate::formation::{self, Cannon};
 use crate::movegen::{MoveGen, MoveList};
+#[cfg(feature = "nnue")]
+use crate::nnue::{Accumulator, Feature, Network};
 use crate::square::Square;
+use crate::tables::{distance_ring, front, shot_blocker, shot_body, shot_targets, sides};
 use crate::transposition::hash::*;
 use std::sync::Arc;
 
@@ -13,6 +18,8 @@ pub struct BoardState {
     pub prev_capture: bool,
     pub prev_state: Option<Arc<BoardState>>,
     pub hash: u64,
+    #[cfg(feature = "nnue")]
+    pub accumulator: Accumulator,
 }
 impl Default for BoardState {
     fn default() -> Self {
@@ -21,6 +28,8 @@ impl Default for BoardState {
             prev_capture: false,
             prev_state: None,
             hash: SIDE, // TODO
+            #[cfg(feature = "nnue")]
+            accumulator: Accumulator::default(),
         }
     }
 }
@@ -31,6 +40,8 @@ impl BoardState {
             prev_capture: false,
             prev_state: self.prev_state(),
             hash: self.hash,
+            #[cfg(feature = "nnue")]
+            accumulator: self.accumulator.clone(),
         }
     }
     pub fn prev_state(&self) -> Option<Arc<Self>> {
@@ -54,12 +65,17 @@ impl Clone for Board {
 
 impl Board {
     pub fn new(color: Color) -> Self {
+        let mut state = BoardState::default();
+        // the side key is included whenever white is to move
+        if color == Color::Black {
+            state.hash ^= SIDE;
+        }
         Self {
             pieces: EMPTY,
             pieces_with_color: [EMPTY; NUM_COLORS],
             side_to_move: color,
             castles: [EMPTY; 2],
-            state: Arc::new(BoardState::default()),
+            state: Arc::new(state),
         }
     }
 
@@ -90,8 +106,26 @@ impl Board {
         board.set(Color::Black, Square::H8);
         board.set(Color::Black, Square::H7);
         board.set(Color::Black, Square::H6);
-        board.castles[0] |= BitBoard::from_square(Square::H1);
-        board.castles[1] |= BitBoard::from_square(Square::A8);
+        board.set_castle(Color::White, Square::H1);
+        board.set_castle(Color::Black, Square::A8);
+        board
+    }
+
+    /// Builds a position from the soldiers and towns of both colors.
+    pub fn from_bitboards(
+        side_to_move: Color,
+        soldiers: [BitBoard; NUM_COLORS],
+        castles: [BitBoard; NUM_COLORS],
+    ) -> Self {
+        let mut board = Board::new(side_to_move);
+        for color in ALL_COLORS {
+            for sq in soldiers[color.to_index()] {
+                board.set(color, sq);
+            }
+            for sq in castles[color.to_index()] {
+                board.set_castle(color, sq);
+            }
+        }
         board
     }
 
@@ -136,6 +170,11 @@ impl Board {
         self.castle_with_color(!self.side_to_move)
     }
 
+    /// Whether `m` captures or shoots the enemy town, which ends the game.
+    pub fn captures_town(&self, m: BitMove) -> bool {
+        (self.enemy_castle() & BitBoard::from_square(m.dst())).is_not_empty()
+    }
+
     pub fn color_on(&self, square: Square) -> Option<Color> {
         if (self.pieces_with_color(Color::White) & BitBoard::from_square(square)) != EMPTY {
             Some(Color::White)
@@ -153,38 +192,76 @@ impl Board {
         self.pieces |= square_bb;
         let mut new_state = self.state.partial_clone();
         new_state.hash ^= PIECES[color.to_index()][square.to_index()];
+        #[cfg(feature = "nnue")]
+        new_state.accumulator.add(Feature::Soldier, color, square);
+        self.state = Arc::new(new_state);
+    }
+
+    /// Places the town of `color` on `square`.
+    pub fn set_castle(&mut self, color: Color, square: Square) {
+        self.castles[color.to_index()] |= BitBoard::from_square(square);
+        let mut new_state = self.state.partial_clone();
+        new_state.hash ^= CASTLES[color.to_index()][square.to_index()];
+        #[cfg(feature = "nnue")]
+        new_state.accumulator.add(Feature::Town, color, square);
         self.state = Arc::new(new_state);
     }
 
     // TODO split this up into seperate parts to also use in undoing moves
     pub fn apply_move(&mut self, m: BitMove) {
-        assert_ne!(m.src(), m.dst());
         let src_bb = BitBoard::from_square(m.src());
         let dst_bb = BitBoard::from_square(m.dst());
 
         let mut new_state = self.state.partial_clone();
 
         new_state.prev_state = Some(Arc::clone(&self.state));
-        if m.is_shot() {
-            self.pieces ^= dst_bb;
-            self.pieces_with_color[(!self.side_to_move).to_index()] ^= dst_bb;
-            new_state.hash ^= PIECES[(!self.side_to_move).to_index()][m.dst().to_index()];
-        } else {
-            self.pieces ^= src_bb;
-            self.pieces |= dst_bb;
-            self.pieces_with_color[self.side_to_move.to_index()] ^= src_bb | dst_bb;
-            new_state.prev_capture =
-                (self.pieces_with_color[(!self.side_to_move).to_index()] & dst_bb).is_not_empty();
-            self.pieces_with_color[(!self.side_to_move).to_index()] &= !dst_bb;
-            // Toggle hash of source and destination square of color to move
-            new_state.hash ^= PIECES[self.side_to_move.to_index()][m.src().to_index()];
-            new_state.hash ^= PIECES[self.side_to_move.to_index()][m.dst().to_index()];
-            // Toggle hash of destination square of opposing color if move was capture
-            if new_state.prev_capture {
+        match m.kind() {
+            // Shooting the town ends the game like capturing it, the town stays where it is
+            MoveKind::Shot if self.captures_town(m) => {}
+            MoveKind::Shot => {
+                self.pieces ^= dst_bb;
+                self.pieces_with_color[(!self.side_to_move).to_index()] ^= dst_bb;
                 new_state.hash ^= PIECES[(!self.side_to_move).to_index()][m.dst().to_index()];
+                #[cfg(feature = "nnue")]
+                new_state
+                    .accumulator
+                    .remove(Feature::Soldier, !self.side_to_move, m.dst());
+            }
+            MoveKind::Placement => {
+                self.castles[self.side_to_move.to_index()] |= dst_bb;
+                new_state.hash ^= CASTLES[self.side_to_move.to_index()][m.dst().to_index()];
+                #[cfg(feature = "nnue")]
+                new_state
+                    .accumulator
+                    .add(Feature::Town, self.side_to_move, m.dst());
+            }
+            kind => {
+                assert_ne!(m.src(), m.dst());
+                self.pieces ^= src_bb;
+                self.pieces |= dst_bb;
+                self.pieces_with_color[self.side_to_move.to_index()] ^= src_bb | dst_bb;
+                // Capturing the town ends the game, so only soldiers have to be restored on undo
+                new_state.prev_capture = kind == MoveKind::Capture && !self.captures_town(m);
+                self.pieces_with_color[(!self.side_to_move).to_index()] &= !dst_bb;
+                // Toggle hash of source and destination square of color to move
+                new_state.hash ^= PIECES[self.side_to_move.to_index()][m.src().to_index()];
+                new_state.hash ^= PIECES[self.side_to_move.to_index()][m.dst().to_index()];
+                // Toggle hash of destination square of opposing color if move was capture
+                if new_state.prev_capture {
+                    new_state.hash ^= PIECES[(!self.side_to_move).to_index()][m.dst().to_index()];
+                }
+                #[cfg(feature = "nnue")]
+                {
+                    let accumulator = &mut new_state.accumulator;
+                    accumulator.remove(Feature::Soldier, self.side_to_move, m.src());
+                    accumulator.add(Feature::Soldier, self.side_to_move, m.dst());
+                    if new_state.prev_capture {
+                        accumulator.remove(Feature::Soldier, !self.side_to_move, m.dst());
+                    }
+                }
             }
-            new_state.hash ^= SIDE;
         }
+        new_state.hash ^= SIDE;
         new_state.prev_move = m;
         self.side_to_move = !self.side_to_move;
         self.state = Arc::new(new_state);
@@ -196,31 +273,82 @@ impl Board {
         let src_bb = BitBoard::from_square(undo_move.src());
         let dst_bb = BitBoard::from_square(undo_move.dst());
 
-        if undo_move.is_shot() {
-            self.pieces ^= dst_bb;
-            self.pieces_with_color[(!self.side_to_move).to_index()] ^= dst_bb;
-        } else {
-            self.pieces ^= src_bb;
-            self.pieces &= !dst_bb;
-            self.pieces_with_color[self.side_to_move.to_index()] ^= src_bb | dst_bb;
-            if self.state.prev_capture {
-                self.pieces |= dst_bb;
-                self.pieces_with_color[(!self.side_to_move).to_index()] |= dst_bb;
+        match undo_move.kind() {
+            MoveKind::Shot if self.captures_town(undo_move) => {}
+            MoveKind::Shot => {
+                self.pieces ^= dst_bb;
+                self.pieces_with_color[(!self.side_to_move).to_index()] ^= dst_bb;
+            }
+            MoveKind::Placement => {
+                self.castles[self.side_to_move.to_index()] &= !dst_bb;
+            }
+            _ => {
+                self.pieces ^= src_bb;
+                self.pieces &= !dst_bb;
+                self.pieces_with_color[self.side_to_move.to_index()] ^= src_bb | dst_bb;
+                if self.state.prev_capture {
+                    self.pieces |= dst_bb;
+                    self.pieces_with_color[(!self.side_to_move).to_index()] |= dst_bb;
+                }
             }
         }
         self.state = self.state.prev_state().unwrap();
     }
 
     pub fn generate_moves(&self) -> MoveList {
-        MoveGen::generate(&self)
+        MoveGen::generate(self)
     }
 
     pub fn generate_captures(&self) -> MoveList {
-        MoveGen::generate_captures(&self)
+        MoveGen::generate_captures(self)
     }
 
     pub fn generate_moves_for(&self, sq: Square) -> Vec<BitMove> {
-        MoveGen::generate(&self).filter(|m| m.src() == sq).collect()
+        MoveGen::generate(self).filter(|m| m.src() == sq).collect()
+    }
+
+    /// All squares `color` threatens with soldier captures or cannon shots.
+    pub fn attacks(&self, color: Color) -> BitBoard {
+        self.attack_map(color).all()
+    }
+
+    pub fn attack_map(&self, color: Color) -> AttackMap {
+        AttackMap::new(self, color)
+    }
+
+    /// Whether the town of `color` can be captured or shot by the opponent, the equivalent of check.
+    /// Only looks at the squares an attack on the town can come from.
+    pub fn town_threatened(&self, color: Color) -> bool {
+        let town = self.castle_with_color(color);
+        if town.is_empty() {
+            return false;
+        }
+        let sq = town.to_square();
+        let enemy = self.pieces_with_color(!color);
+        // enemy soldiers capture forward or sideways, so from in front of the town or beside it
+        if ((front(color, sq) | sides(color, sq)) & enemy).is_not_empty() {
+            return true;
+        }
+        // cannon heads shoot two or three squares far
+        let unoccupied = !self.pieces_with_castles();
+        for src in (distance_ring(sq, 2) | distance_ring(sq, 3)) & enemy {
+            for d in 0..8 {
+                let body = shot_body(src, d);
+                if (shot_targets(src, d) & town).is_not_empty()
+                    && (shot_blocker(src, d) & unoccupied).is_not_empty()
+                    && body.is_not_empty()
+                    && (body & enemy) == body
+                {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    /// All cannons of `color` with their orientation, slides and shot targets.
+    pub fn cannons(&self, color: Color) -> Vec<Cannon> {
+        formation::cannons(self, color)
     }
 
     pub fn last_capture(&self) -> bool {
@@ -235,4 +363,17 @@ impl Board {
     pub fn hash(&self) -> u64 {
         self.state.hash
     }
+    /// The network inputs of the current position, restored by `undo_move` with the state.
+    #[cfg(feature = "nnue")]
+    pub fn accumulator(&self) -> &Accumulator {
+        &self.state.accumulator
+    }
+    /// Keeps the inputs of `network` up to date from now on, positions before
+    /// the current one are left without them.
+    #[cfg(feature = "nnue")]
+    pub fn set_network(&mut self, network: Arc<Network>) {
+        let mut state = BoardState::clone(&self.state);
+        state.accumulator = Accumulator::from_board(self, network);
+        self.state = Arc::new(state);
+    }
 }
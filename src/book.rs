@@ -0,0 +1,314 @@
+use crate::board::Board;
+use crate::cannon_move::BitMove;
+use crate::engine_match::{
+    opening_index, opening_position, random_opening_lines, run_match, MatchConfig, MatchGame,
+};
+use crate::player::Player;
+use crate::record::{GameRecord, GameResult};
+use crate::rng::Rng;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Write;
+use std::path::Path;
+
+const MAGIC: &[u8; 8] = b"CNBOOK01";
+/// Bytes of a position header, hash and number of moves
+const POSITION_SIZE: usize = 8 + 2;
+/// Bytes of a move with its weight and results
+const MOVE_SIZE: usize = 2 + 4 * 4;
+
+#[derive(Debug)]
+pub enum BookError {
+    Io(std::io::Error),
+    InvalidFile(String),
+}
+
+impl fmt::Display for BookError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BookError::Io(e) => write!(f, "{}", e),
+            BookError::InvalidFile(s) => write!(f, "invalid book file: {}", s),
+        }
+    }
+}
+
+impl std::error::Error for BookError {}
+
+impl From<std::io::Error> for BookError {
+    fn from(e: std::io::Error) -> Self {
+        BookError::Io(e)
+    }
+}
+
+/// A move of the book, results are counted for the player making the move.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BookMove {
+    pub mv: BitMove,
+    /// Relative chance of playing the move, 0 keeps it out of play
+    pub weight: u32,
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+impl BookMove {
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+}
+
+/// Moves for positions keyed by `Board::hash()`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Book {
+    positions: HashMap<u64, Vec<BookMove>>,
+}
+
+impl Book {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of positions in the book.
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    pub fn moves(&self, board: &Board) -> &[BookMove] {
+        self.positions
+            .get(&board.hash())
+            .map_or(&[], |moves| moves.as_slice())
+    }
+
+    /// Counts the first `max_plies` moves of the game with its result.
+    pub fn add_game(&mut self, record: &GameRecord, max_plies: usize) {
+        for (board, m) in record.positions().into_iter().take(max_plies) {
+            let moves = self.positions.entry(board.hash()).or_default();
+            let index = match moves.iter().position(|b| b.mv == m) {
+                Some(index) => index,
+                None => {
+                    moves.push(BookMove {
+                        mv: m,
+                        weight: 0,
+                        wins: 0,
+                        draws: 0,
+                        losses: 0,
+                    });
+                    moves.len() - 1
+                }
+            };
+            let entry = &mut moves[index];
+            let mover = board.side_to_move();
+            if record.result == GameResult::Draw {
+                entry.draws += 1;
+            } else if record.result == GameResult::win_for(mover) {
+                entry.wins += 1;
+            } else {
+                entry.losses += 1;
+            }
+        }
+    }
+
+    /// Builds a book from the openings of the games. Moves played in fewer than `min_games`
+    /// games are dropped, the others are weighted by the points they scored.
+    pub fn from_records(records: &[GameRecord], max_plies: usize, min_games: u32) -> Self {
+        let mut book = Self::new();
+        for record in records {
+            book.add_game(record, max_plies);
+        }
+        for moves in book.positions.values_mut() {
+            moves.retain(|m| m.games() >= min_games.max(1));
+            for m in moves.iter_mut() {
+                m.weight = 2 * m.wins + m.draws;
+            }
+            moves.sort_by_key(|m| std::cmp::Reverse(m.weight));
+        }
+        book.positions.retain(|_, moves| !moves.is_empty());
+        book
+    }
+
+    /// Picks a legal book move at random by weight, None if the position is not in the book.
+    pub fn choose(&self, board: &Board, rng: &mut Rng) -> Option<BitMove> {
+        let legal = board.generate_moves();
+        // a hash collision could bring up moves of another position
+        let moves: Vec<&BookMove> = self
+            .moves(board)
+            .iter()
+            .filter(|m| m.weight > 0 && legal.contains(m.mv))
+            .collect();
+        let total: u64 = moves.iter().map(|m| m.weight as u64).sum();
+        if total == 0 {
+            return None;
+        }
+        let mut pick = rng.below(total as usize) as u64;
+        for m in moves {
+            if pick < m.weight as u64 {
+                return Some(m.mv);
+            }
+            pick -= m.weight as u64;
+        }
+        None
+    }
+
+    /// Positions are sorted by hash so the same book always gives the same file.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut hashes: Vec<&u64> = self.positions.keys().collect();
+        hashes.sort_unstable();
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend((hashes.len() as u64).to_le_bytes());
+        for hash in hashes {
+            let moves = &self.positions[hash];
+            bytes.extend(hash.to_le_bytes());
+            bytes.extend((moves.len() as u16).to_le_bytes());
+            for m in moves {
+                bytes.extend(m.mv.data.to_le_bytes());
+                for value in [m.weight, m.wins, m.draws, m.losses] {
+                    bytes.extend(value.to_le_bytes());
+                }
+            }
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BookError> {
+        let invalid = |s: &str| BookError::InvalidFile(s.to_string());
+        let truncated = || invalid("truncated");
+        let take = |pos: &mut usize, n: usize| -> Result<&[u8], BookError> {
+            let slice = bytes.get(*pos..*pos + n).ok_or_else(truncated)?;
+            *pos += n;
+            Ok(slice)
+        };
+        let u32_at =
+            |slice: &[u8], i: usize| u32::from_le_bytes(slice[i..i + 4].try_into().unwrap());
+
+        let mut pos = 0;
+        if take(&mut pos, MAGIC.len())? != MAGIC {
+            return Err(invalid("wrong magic"));
+        }
+        let count = u64::from_le_bytes(take(&mut pos, 8)?.try_into().unwrap());
+        let mut book = Self::new();
+        for _ in 0..count {
+            let header = take(&mut pos, POSITION_SIZE)?;
+            let hash = u64::from_le_bytes(header[..8].try_into().unwrap());
+            let num_moves = u16::from_le_bytes([header[8], header[9]]) as usize;
+            let mut moves = Vec::with_capacity(num_moves);
+            for _ in 0..num_moves {
+                let m = take(&mut pos, MOVE_SIZE)?;
+                moves.push(BookMove {
+                    mv: BitMove::new(u16::from_le_bytes([m[0], m[1]])),
+                    weight: u32_at(m, 2),
+                    wins: u32_at(m, 6),
+                    draws: u32_at(m, 10),
+                    losses: u32_at(m, 14),
+                });
+            }
+            book.positions.insert(hash, moves);
+        }
+        if pos != bytes.len() {
+            return Err(invalid("trailing bytes"));
+        }
+        Ok(book)
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, BookError> {
+        Self::from_bytes(&std::fs::read(path)?)
+    }
+
+    pub fn write_file<P: AsRef<Path>>(&self, path: P) -> Result<(), BookError> {
+        std::fs::File::create(path)?.write_all(&self.to_bytes())?;
+        Ok(())
+    }
+}
+
+/// Games of `player` against itself from openings of `random_plies` random moves. The records
+/// start from the start position with the random moves, so a book of them covers the opening.
+pub fn selfplay_records<P, F>(
+    player: P,
+    random_plies: usize,
+    config: &MatchConfig,
+    mut on_game: F,
+) -> Vec<GameRecord>
+where
+    P: Fn() -> Box<dyn Player> + Sync,
+    F: FnMut(&MatchGame),
+{
+    // every opening is played with both colors
+    let lines = random_opening_lines(config.games.div_ceil(2).max(1), random_plies, 0);
+    let openings: Vec<Board> = lines.iter().map(|line| opening_position(line)).collect();
+    let mut records = Vec::new();
+    run_match(&player, &player, &openings, config, |game, _| {
+        on_game(game);
+        let line = &lines[opening_index(game.index, lines.len())];
+        let moves = line.iter().chain(&game.record.moves).copied().collect();
+        records.push(GameRecord::new(
+            &Board::start_position(),
+            moves,
+            game.record.result,
+        ));
+    });
+    records
+}
+
+#[test]
+fn book_test() {
+    use crate::notation::parse_move;
+    use crate::player::GreedyPlayer;
+    use crate::search::SearchLimits;
+
+    let start = Board::start_position();
+    let game = |moves: &[&str], result: GameResult| {
+        let mut board = start.shallow_clone();
+        let mut parsed = Vec::new();
+        for m in moves {
+            let m = parse_move(&board, m).unwrap();
+            board.apply_move(m);
+            parsed.push(m);
+        }
+        GameRecord::new(&start, parsed, result)
+    };
+    let records = vec![
+        game(&["c3d4", "d6d5"], GameResult::WhiteWin),
+        game(&["c3d4", "d6d5"], GameResult::WhiteWin),
+        game(&["c3d4", "b6b5"], GameResult::Draw),
+        game(&["e3e4"], GameResult::BlackWin),
+    ];
+    let book = Book::from_records(&records, 1, 1);
+    assert_eq!(book.len(), 1);
+    let moves = book.moves(&start);
+    assert_eq!(moves.len(), 2);
+    assert_eq!(moves[0].mv, parse_move(&start, "c3d4").unwrap());
+    assert_eq!((moves[0].wins, moves[0].draws, moves[0].weight), (2, 1, 5));
+    // a move that only lost is never played
+    assert_eq!(moves[1].weight, 0);
+    let mut rng = Rng::new(1);
+    for _ in 0..10 {
+        assert_eq!(book.choose(&start, &mut rng), Some(moves[0].mv));
+    }
+
+    let deeper = Book::from_records(&records, 2, 2);
+    assert_eq!(deeper.len(), 2);
+    assert_eq!(Book::from_bytes(&deeper.to_bytes()).unwrap(), deeper);
+    assert!(Book::from_bytes(&deeper.to_bytes()[..20]).is_err());
+    assert!(book
+        .choose(&Board::from_fen("B7/8/8/8/8/8/8/7W w").unwrap(), &mut rng)
+        .is_none());
+
+    // self-play games are recorded from the start position with their random moves
+    let config = MatchConfig {
+        limits: SearchLimits::depth(1),
+        games: 4,
+        max_plies: 20,
+        ..MatchConfig::default()
+    };
+    let records = selfplay_records(|| Box::new(GreedyPlayer::new()), 2, &config, |_| {});
+    assert_eq!(records.len(), 4);
+    assert!(records.iter().all(|r| r.start == start.to_fen()));
+    let book = Book::from_records(&records, 4, 1);
+    let legal = start.generate_moves();
+    assert!(book
+        .choose(&start, &mut rng)
+        .is_some_and(|m| legal.contains(m)));
+}
@@ -5,7 +5,47 @@ use std::fmt;
 
 const SRC_MASK: u16 = 0b0000_000000_111111;
 const DST_MASK: u16 = 0b0000_111111_000000;
-const SHOT_MASK: u16 = 0b0001_000000_000000;
+const KIND_MASK: u16 = 0b0111_000000_000000;
+const KIND_SHIFT: u16 = 12;
+
+/// What kind of move a `BitMove` is, stored in the spare bits above src and dst.
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MoveKind {
+    /// A soldier steps forward onto an empty square
+    Step = 0,
+    /// A soldier steps forward or sideways onto an enemy soldier or town
+    Capture = 1,
+    /// A threatened soldier retreats two squares backwards
+    Retreat = 2,
+    /// The tail of a cannon slides to the empty square in front of its head
+    CannonMove = 3,
+    /// A cannon shoots an enemy soldier or town without moving
+    Shot = 4,
+    /// A town is placed on its home rank
+    Placement = 5,
+}
+
+pub const NUM_MOVE_KINDS: usize = 6;
+pub const ALL_MOVE_KINDS: [MoveKind; NUM_MOVE_KINDS] = [
+    MoveKind::Step,
+    MoveKind::Capture,
+    MoveKind::Retreat,
+    MoveKind::CannonMove,
+    MoveKind::Shot,
+    MoveKind::Placement,
+];
+
+impl MoveKind {
+    pub const fn from_index(i: u8) -> Self {
+        assert!(i < NUM_MOVE_KINDS as u8);
+        ALL_MOVE_KINDS[i as usize]
+    }
+
+    pub const fn to_index(self) -> usize {
+        self as usize
+    }
+}
 
 #[derive(Copy, Clone, PartialEq, Eq, Default)]
 #[repr(transparent)]
@@ -23,15 +63,22 @@ impl BitMove {
     }
 
     pub const fn make(src: Square, dst: Square) -> Self {
+        Self::make_with_kind(src, dst, MoveKind::Step)
+    }
+
+    pub const fn make_with_kind(src: Square, dst: Square, kind: MoveKind) -> Self {
         Self {
-            data: src.0 as u16 | ((dst.0 as u16) << 6),
+            data: src.0 as u16 | ((dst.0 as u16) << 6) | ((kind as u16) << KIND_SHIFT),
         }
     }
 
     pub const fn make_shot(src: Square, dst: Square) -> Self {
-        Self {
-            data: src.0 as u16 | ((dst.0 as u16) << 6) | (1 << 12),
-        }
+        Self::make_with_kind(src, dst, MoveKind::Shot)
+    }
+
+    /// Placing a town uses the same square as source and destination.
+    pub const fn make_placement(dst: Square) -> Self {
+        Self::make_with_kind(dst, dst, MoveKind::Placement)
     }
 
     pub const fn dst(self) -> Square {
@@ -65,8 +112,21 @@ impl BitMove {
         self.src().file()
     }
 
+    pub const fn kind(self) -> MoveKind {
+        MoveKind::from_index(((self.data & KIND_MASK) >> KIND_SHIFT) as u8)
+    }
+
     pub const fn is_shot(self) -> bool {
-        (self.data & SHOT_MASK) != 0
+        matches!(self.kind(), MoveKind::Shot)
+    }
+
+    /// Whether the move removes an enemy soldier or town, either by moving onto it or by shooting it.
+    pub const fn is_capture(self) -> bool {
+        matches!(self.kind(), MoveKind::Capture | MoveKind::Shot)
+    }
+
+    pub const fn is_placement(self) -> bool {
+        matches!(self.kind(), MoveKind::Placement)
     }
 }
 
@@ -76,6 +136,12 @@ impl fmt::Display for BitMove {
     }
 }
 
+impl fmt::Debug for BitMove {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} -> {} ({:?})", self.src(), self.dst(), self.kind())
+    }
+}
+
 #[derive(Eq, Copy, Clone)]
 pub struct MoveWithScore {
     pub bit_move: BitMove,
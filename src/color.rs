@@ -1,6 +1,6 @@
 use std::fmt;
 #[repr(u8)]
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
 pub enum Color {
     White = 0,
     Black = 1,
@@ -28,8 +28,8 @@ impl core::ops::Not for Color {
 impl fmt::Display for Color {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Color::White => write!(f, "{}", "w"),
-            Color::Black => write!(f, "{}", "b"),
+            Color::White => write!(f, "w"),
+            Color::Black => write!(f, "b"),
         }
     }
 }
@@ -125,18 +125,17 @@ impl Rank {
 }
 
 pub const LEFT_TABLE: [Option<File>; 8] = [
+    None,
+    Some(File::A),
     Some(File::B),
     Some(File::C),
     Some(File::D),
     Some(File::E),
     Some(File::F),
     Some(File::G),
-    Some(File::H),
-    None,
 ];
 
 pub const RIGHT_TABLE: [Option<File>; 8] = [
-    None,
     Some(File::B),
     Some(File::C),
     Some(File::D),
@@ -144,26 +143,27 @@ pub const RIGHT_TABLE: [Option<File>; 8] = [
     Some(File::F),
     Some(File::G),
     Some(File::H),
+    None,
 ];
 
 pub const DOWN_TABLE: [Option<Rank>; 8] = [
+    None,
+    Some(Rank::One),
     Some(Rank::Two),
     Some(Rank::Three),
     Some(Rank::Four),
     Some(Rank::Five),
     Some(Rank::Six),
     Some(Rank::Seven),
-    Some(Rank::Eight),
-    None,
 ];
 
 pub const UP_TABLE: [Option<Rank>; 8] = [
-    None,
-    Some(Rank::One),
     Some(Rank::Two),
     Some(Rank::Three),
     Some(Rank::Four),
     Some(Rank::Five),
     Some(Rank::Six),
     Some(Rank::Seven),
+    Some(Rank::Eight),
+    None,
 ];
@@ -0,0 +1,496 @@
+use crate::board::Board;
+use crate::cannon_move::BitMove;
+use crate::color::Color;
+use crate::player::Player;
+use crate::record::{GameRecord, GameResult, RecordError};
+use crate::rng::Rng;
+use crate::search::SearchLimits;
+use std::fmt;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// Plays one game from `start`. A null or illegal move loses, taking the town wins
+/// and the game is drawn after `max_plies` moves.
+pub fn play_game(
+    white: &mut dyn Player,
+    black: &mut dyn Player,
+    start: &Board,
+    limits: &SearchLimits,
+    max_plies: usize,
+) -> GameRecord {
+    let mut board = start.shallow_clone();
+    let mut moves = Vec::new();
+    let result = loop {
+        if moves.len() >= max_plies {
+            break GameResult::Draw;
+        }
+        let mover = board.side_to_move();
+        let player: &mut dyn Player = match mover {
+            Color::White => &mut *white,
+            Color::Black => &mut *black,
+        };
+        let m = player.choose_move(&board, limits);
+        if m == BitMove::null() || !board.generate_moves().contains(m) {
+            break GameResult::win_for(!mover);
+        }
+        let won = board.captures_town(m);
+        board.apply_move(m);
+        moves.push(m);
+        if won {
+            break GameResult::win_for(mover);
+        }
+    };
+    GameRecord::new(start, moves, result)
+}
+
+/// Reads one position per line, empty lines and lines starting with `#` are skipped.
+pub fn read_openings<P: AsRef<Path>>(path: P) -> Result<Vec<Board>, RecordError> {
+    std::fs::read_to_string(path)?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| Ok(Board::from_fen(line)?))
+        .collect()
+}
+
+/// Openings of `plies` random moves from the start position, the same seed gives the same suite.
+pub fn random_openings(count: usize, plies: usize, seed: u64) -> Vec<Board> {
+    random_opening_lines(count, plies, seed)
+        .iter()
+        .map(|line| opening_position(line))
+        .collect()
+}
+
+/// The moves of `random_openings`, for games that have to be recorded from the start position.
+pub fn random_opening_lines(count: usize, plies: usize, seed: u64) -> Vec<Vec<BitMove>> {
+    let mut rng = Rng::new(seed);
+    let mut lines = Vec::with_capacity(count);
+    while lines.len() < count {
+        let mut board = Board::start_position();
+        let mut line = Vec::with_capacity(plies);
+        let mut decided = false;
+        for _ in 0..plies {
+            let moves = board.generate_moves();
+            if moves.is_empty() {
+                decided = true;
+                break;
+            }
+            let m = moves.get(rng.below(moves.len()));
+            decided = board.captures_town(m);
+            if decided {
+                break;
+            }
+            board.apply_move(m);
+            line.push(m);
+        }
+        if !decided {
+            lines.push(line);
+        }
+    }
+    lines
+}
+
+/// The position after the moves from the start position.
+pub fn opening_position(line: &[BitMove]) -> Board {
+    let mut board = Board::start_position();
+    for m in line {
+        board.apply_move(*m);
+    }
+    // a fresh board so the suite does not keep the move history
+    Board::from_fen(&board.to_fen()).unwrap()
+}
+
+/// Index of the opening game `index` of a match is played from.
+pub fn opening_index(index: usize, openings: usize) -> usize {
+    (index / 2) % openings
+}
+
+/// Wins, draws and losses of the first engine.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Score {
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+impl Score {
+    pub fn add(&mut self, result: GameResult, color: Color) {
+        if result == GameResult::Draw {
+            self.draws += 1;
+        } else if result == GameResult::win_for(color) {
+            self.wins += 1;
+        } else {
+            self.losses += 1;
+        }
+    }
+
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    /// Points per game, between 0 and 1.
+    pub fn mean(&self) -> f64 {
+        (self.wins as f64 + self.draws as f64 / 2.0) / self.games().max(1) as f64
+    }
+
+    /// Variance of the result of a single game.
+    pub fn variance(&self) -> f64 {
+        let mean = self.mean();
+        let n = self.games().max(1) as f64;
+        (self.wins as f64 * (1.0 - mean).powi(2)
+            + self.draws as f64 * (0.5 - mean).powi(2)
+            + self.losses as f64 * mean.powi(2))
+            / n
+    }
+
+    /// The Elo difference and the half width of its 95% confidence interval.
+    pub fn elo(&self) -> (f64, f64) {
+        let mean = self.mean();
+        let error = 1.96 * (self.variance() / self.games().max(1) as f64).sqrt();
+        let low = score_to_elo(mean - error);
+        let high = score_to_elo(mean + error);
+        (score_to_elo(mean), (high - low) / 2.0)
+    }
+}
+
+impl fmt::Display for Score {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (elo, error) = self.elo();
+        write!(
+            f,
+            "W/D/L {}/{}/{}, Elo {:.1} +/- {:.1}",
+            self.wins, self.draws, self.losses, elo, error
+        )
+    }
+}
+
+/// Elo difference for the expected score, clamped so decided matches stay finite.
+pub fn score_to_elo(score: f64) -> f64 {
+    let score = score.clamp(0.001, 0.999);
+    -400.0 * (1.0 / score - 1.0).log10()
+}
+
+pub fn elo_to_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SprtStatus {
+    Continue,
+    /// The Elo difference is `elo0` rather than `elo1`
+    AcceptH0,
+    /// The Elo difference is `elo1` rather than `elo0`
+    AcceptH1,
+}
+
+/// Sequential probability ratio test of `elo0` against `elo1`,
+/// with the normal approximation of the log likelihood ratio.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Sprt {
+    pub elo0: f64,
+    pub elo1: f64,
+    pub alpha: f64,
+    pub beta: f64,
+}
+
+impl Sprt {
+    pub fn new(elo0: f64, elo1: f64) -> Self {
+        Self {
+            elo0,
+            elo1,
+            alpha: 0.05,
+            beta: 0.05,
+        }
+    }
+
+    pub fn bounds(&self) -> (f64, f64) {
+        (
+            (self.beta / (1.0 - self.alpha)).ln(),
+            ((1.0 - self.beta) / self.alpha).ln(),
+        )
+    }
+
+    pub fn llr(&self, score: &Score) -> f64 {
+        if score.games() == 0 {
+            return 0.0;
+        }
+        // one extra win and loss keep the variance positive while every game had the same result
+        let variance = Score {
+            wins: score.wins + 1,
+            draws: score.draws,
+            losses: score.losses + 1,
+        }
+        .variance();
+        let s0 = elo_to_score(self.elo0);
+        let s1 = elo_to_score(self.elo1);
+        score.games() as f64 * (s1 - s0) * (2.0 * score.mean() - s0 - s1) / (2.0 * variance)
+    }
+
+    pub fn status(&self, score: &Score) -> SprtStatus {
+        let llr = self.llr(score);
+        let (lower, upper) = self.bounds();
+        if llr <= lower {
+            SprtStatus::AcceptH0
+        } else if llr >= upper {
+            SprtStatus::AcceptH1
+        } else {
+            SprtStatus::Continue
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct MatchConfig {
+    pub limits: SearchLimits,
+    /// Number of games, every opening is played twice with swapped colors
+    pub games: usize,
+    pub max_plies: usize,
+    /// Games played at the same time
+    pub concurrency: usize,
+    pub sprt: Option<Sprt>,
+}
+
+impl Default for MatchConfig {
+    fn default() -> Self {
+        Self {
+            limits: SearchLimits::depth(6),
+            games: 100,
+            max_plies: 300,
+            concurrency: 1,
+            sprt: None,
+        }
+    }
+}
+
+/// Random moves of the openings played when no openings file is given
+pub const OPENING_PLIES: usize = 6;
+
+/// Command line options shared by the match and tournament binaries.
+pub struct MatchArgs {
+    /// Games, of the match or of every pairing
+    pub games: usize,
+    pub concurrency: usize,
+    pub limits: SearchLimits,
+    openings: Option<String>,
+}
+
+impl MatchArgs {
+    pub fn new(games: usize, concurrency: usize, limits: SearchLimits) -> Self {
+        Self {
+            games,
+            concurrency,
+            limits,
+            openings: None,
+        }
+    }
+
+    /// Parses the option at the start of `args` and returns the number of arguments it took,
+    /// 0 if it is not one of the shared options.
+    pub fn parse(&mut self, args: &[String]) -> Result<usize, String> {
+        let Some(flag) = args.first() else {
+            return Ok(0);
+        };
+        if ![
+            "--games",
+            "--concurrency",
+            "--depth",
+            "--nodes",
+            "--movetime",
+            "--openings",
+        ]
+        .contains(&flag.as_str())
+        {
+            return Ok(0);
+        }
+        let value = args
+            .get(1)
+            .ok_or_else(|| format!("missing value of {}", flag))?;
+        let number = || -> Result<u64, String> {
+            value
+                .parse()
+                .map_err(|_| format!("invalid value of {}: {}", flag, value))
+        };
+        match flag.as_str() {
+            "--games" => self.games = number()? as usize,
+            "--concurrency" => self.concurrency = number()? as usize,
+            "--depth" => self.limits = SearchLimits::depth(number()? as u8),
+            "--nodes" => self.limits = SearchLimits::nodes(number()?),
+            "--movetime" => self.limits = SearchLimits::time(Duration::from_millis(number()?)),
+            _ => self.openings = Some(value.clone()),
+        }
+        Ok(2)
+    }
+
+    /// The positions of the openings file, or random openings for playing every one with
+    /// both colors.
+    pub fn openings(&self) -> Result<Vec<Board>, String> {
+        match &self.openings {
+            Some(path) => read_openings(path)
+                .map_err(|e| format!("Could not read openings from {}: {}", path, e)),
+            None => Ok(random_openings(self.games.div_ceil(2), OPENING_PLIES, 0)),
+        }
+    }
+}
+
+/// A finished game of a match.
+pub struct MatchGame {
+    pub index: usize,
+    pub first_color: Color,
+    pub record: GameRecord,
+}
+
+/// Plays the first engine against the second over the openings. Every thread creates its
+/// own players. `on_game` is called on the calling thread after every game with the score
+/// so far. The match stops early once the SPRT accepts a hypothesis, games still running
+/// then are counted in the score but cannot change the decision.
+pub fn run_match<A, B, F>(
+    first: A,
+    second: B,
+    openings: &[Board],
+    config: &MatchConfig,
+    mut on_game: F,
+) -> (Score, SprtStatus)
+where
+    A: Fn() -> Box<dyn Player> + Sync,
+    B: Fn() -> Box<dyn Player> + Sync,
+    F: FnMut(&MatchGame, &Score),
+{
+    assert!(!openings.is_empty());
+    let next = AtomicUsize::new(0);
+    let stop = AtomicBool::new(false);
+    let mut score = Score::default();
+    let mut status = SprtStatus::Continue;
+
+    std::thread::scope(|scope| {
+        let (sender, receiver) = mpsc::channel();
+        for _ in 0..config.concurrency.max(1) {
+            let sender = sender.clone();
+            let (next, stop, first, second) = (&next, &stop, &first, &second);
+            scope.spawn(move || {
+                let mut a = first();
+                let mut b = second();
+                loop {
+                    let index = next.fetch_add(1, Ordering::SeqCst);
+                    if index >= config.games || stop.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    let start = &openings[opening_index(index, openings.len())];
+                    let first_color = if index % 2 == 0 {
+                        Color::White
+                    } else {
+                        Color::Black
+                    };
+                    a.new_game();
+                    b.new_game();
+                    let record = match first_color {
+                        Color::White => {
+                            play_game(&mut *a, &mut *b, start, &config.limits, config.max_plies)
+                        }
+                        Color::Black => {
+                            play_game(&mut *b, &mut *a, start, &config.limits, config.max_plies)
+                        }
+                    };
+                    let game = MatchGame {
+                        index,
+                        first_color,
+                        record,
+                    };
+                    if sender.send(game).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+
+        for game in receiver {
+            score.add(game.record.result, game.first_color);
+            on_game(&game, &score);
+            if let (Some(sprt), SprtStatus::Continue) = (config.sprt, status) {
+                status = sprt.status(&score);
+                if status != SprtStatus::Continue {
+                    stop.store(true, Ordering::SeqCst);
+                }
+            }
+        }
+    });
+    (score, status)
+}
+
+#[test]
+fn engine_match_test() {
+    use crate::player::{GreedyPlayer, RandomPlayer};
+
+    let score = Score {
+        wins: 180,
+        draws: 60,
+        losses: 60,
+    };
+    assert_eq!(score.mean(), 0.7);
+    let (elo, error) = score.elo();
+    assert!((elo - 147.2).abs() < 0.1);
+    assert!(error > 0.0 && error < elo);
+    let sprt = Sprt::new(0.0, 10.0);
+    assert_eq!(sprt.status(&score), SprtStatus::AcceptH1);
+    let weak = Score {
+        wins: 60,
+        draws: 60,
+        losses: 180,
+    };
+    assert_eq!(sprt.status(&weak), SprtStatus::AcceptH0);
+
+    let openings = random_openings(2, 4, 1);
+    assert_eq!(openings.len(), 2);
+    let config = MatchConfig {
+        limits: SearchLimits::depth(1),
+        games: 4,
+        max_plies: 40,
+        concurrency: 2,
+        sprt: None,
+    };
+    let mut records = Vec::new();
+    let (score, _) = run_match(
+        || Box::new(GreedyPlayer::new()),
+        || Box::new(RandomPlayer::new(1)),
+        &openings,
+        &config,
+        |game, _| records.push((game.index, game.record.clone())),
+    );
+    assert_eq!(score.games(), 4);
+    assert_eq!(records.len(), 4);
+    // the first engine has white in even games
+    records.sort_by_key(|(index, _)| *index);
+    assert_eq!(records[0].1.start, records[1].1.start);
+    assert_eq!(records[0].1.start, openings[0].to_fen());
+
+    // a decided test stays decided while the games in flight finish
+    let config = MatchConfig {
+        games: 200,
+        concurrency: 4,
+        sprt: Some(Sprt::new(0.0, 400.0)),
+        ..config
+    };
+    let (score, status) = run_match(
+        || Box::new(GreedyPlayer::new()),
+        || Box::new(RandomPlayer::new(1)),
+        &openings,
+        &config,
+        |_, _| {},
+    );
+    assert_eq!(status, SprtStatus::AcceptH1);
+    assert!(score.games() < 200);
+
+    let args: Vec<String> = ["--games", "4", "--depth", "2", "--depth", "x", "--sprt"]
+        .iter()
+        .map(|a| a.to_string())
+        .collect();
+    let mut match_args = MatchArgs::new(10, 1, SearchLimits::depth(6));
+    assert_eq!(match_args.parse(&args[0..]), Ok(2));
+    assert_eq!(match_args.parse(&args[2..]), Ok(2));
+    assert!(match_args.parse(&args[4..]).is_err());
+    assert_eq!(match_args.parse(&args[6..]), Ok(0));
+    assert_eq!((match_args.games, match_args.limits.depth), (4, 2));
+    assert_eq!(match_args.openings().unwrap().len(), 2);
+}
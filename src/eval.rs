@@ -1,32 +1,204 @@
 use crate::bitboard::BitBoard;
 use crate::board::Board;
-use crate::color::Color;
+use crate::color::{Color, NUM_COLORS};
+use crate::formation::{visit_cannons, Orientation};
+use crate::movegen::MoveGen;
+#[cfg(feature = "nnue")]
+use crate::nnue::Network;
 use crate::square::Square;
-use crate::tables::{distance_ring, distance_square};
+use crate::tables::{distance_ring, distance_square, shot_blocker, shot_targets};
+use std::fmt;
+#[cfg(feature = "nnue")]
+use std::sync::Arc;
+
+define_params! {
+    /// Weights of the evaluation terms.
+    EvalParams {
+        piece_value: i16 = 8,
+        area_weight: i16 = 1,
+        surround_weight: i16 = 1,
+        /// Divides the surround score to reduce double counting a bit
+        surround_divisor: i16 = 2,
+        castle_distance_weight: i16 = 1,
+        /// Distance to the nearest castle that is still free of penalty
+        castle_cutoff: i16 = 3,
+        castle_enemy_importance: i16 = 1,
+        castle_my_importance: i16 = 0,
+        /// Value of every cannon by orientation
+        cannon_diagonal: i16 = 3,
+        cannon_vertical: i16 = 2,
+        cannon_horizontal: i16 = 1,
+        /// Value of every enemy soldier a cannon can shoot
+        aim_soldier: i16 = 2,
+        /// Value of a cannon that can shoot the enemy town
+        aim_town: i16 = 6,
+        mobility_weight: i16 = 1,
+        /// Divides the number of moves before weighting it
+        mobility_divisor: i16 = 4,
+        /// Penalty for every enemy soldier within two squares of the own town
+        town_attacker: i16 = 3,
+        /// Bonus for every own soldier within two squares of the own town
+        town_defender: i16 = 1,
+        /// Penalty for every empty line next to the town a cannon could shoot along
+        town_open_line: i16 = 1,
+    }
+}
 
-const PIECE_VALUE: i16 = 8;
 // TODO what to evaluate
-// cannons -> diagonal > vertical > horizontal
-// "aiming" to opponents side
-// mobility -> how to evaluate
 // overall capture score
 #[inline]
 pub fn eval(board: &Board) -> i16 {
-    let me = board.player_pieces();
-    let my_castle = board.castle_with_color(board.side_to_move()).to_square();
-    let enemy = board.enemy_pieces();
-    let enemy_castle = board.enemy_castle().to_square();
-    let pc = piece_count(me) - piece_count(enemy);
-    let area = match board.side_to_move() {
-        Color::White => area(me) - area(enemy.reverse()),
-        Color::Black => area(me.reverse()) - area(enemy),
-    };
-    // div 2 to reduce double counting a bit
-    let surround = surround(me, enemy) / 2;
-    let castle_distance = castle_distance(me, my_castle, enemy_castle)
-        - castle_distance(enemy, enemy_castle, my_castle);
-    // TODO come up with a way to normalize these parameters
-    (PIECE_VALUE * pc) + area + surround + castle_distance
+    eval_with(board, &EvalParams::default())
+}
+
+/// Evaluates from the side to move with the `network`, or with the handcrafted terms without one.
+#[cfg(feature = "nnue")]
+#[inline]
+pub fn eval_with_network(
+    board: &Board,
+    params: &EvalParams,
+    network: Option<&Arc<Network>>,
+) -> i16 {
+    match network {
+        Some(network) => crate::nnue::evaluate(network, board),
+        None => eval_with(board, params),
+    }
+}
+
+/// The handcrafted evaluation from the side to move.
+#[inline]
+pub fn eval_with(board: &Board, params: &EvalParams) -> i16 {
+    let terms = color_terms(board, params);
+    let me = board.side_to_move().to_index();
+    let enemy = (!board.side_to_move()).to_index();
+    terms.iter().map(|t| t[me] - t[enemy]).sum()
+}
+
+pub const NUM_TERMS: usize = 8;
+pub const TERM_NAMES: [&str; NUM_TERMS] = [
+    "pieces",
+    "area",
+    "surround",
+    "castle_distance",
+    "cannons",
+    "aim",
+    "mobility",
+    "town_safety",
+];
+
+/// The weighted score of every term for both colors, indexed by term and color.
+#[inline]
+fn color_terms(board: &Board, params: &EvalParams) -> [[i16; NUM_COLORS]; NUM_TERMS] {
+    let white = board.pieces_with_color(Color::White);
+    let black = board.pieces_with_color(Color::Black);
+    let white_castle = board.castle_with_color(Color::White).to_square();
+    let black_castle = board.castle_with_color(Color::Black).to_square();
+    // div to reduce double counting a bit
+    let surround_divisor = params.surround_divisor.max(1);
+    // surround already weighs both colors against each other, so it is only scored for the side to move
+    let mut surround_term = [0; NUM_COLORS];
+    surround_term[board.side_to_move().to_index()] = params.surround_weight
+        * (surround(board.player_pieces(), board.enemy_pieces()) / surround_divisor);
+    let (white_cannons, white_aim) = cannons(board, Color::White, params);
+    let (black_cannons, black_aim) = cannons(board, Color::Black, params);
+    [
+        [
+            params.piece_value * piece_count(white),
+            params.piece_value * piece_count(black),
+        ],
+        [
+            params.area_weight * area(white),
+            params.area_weight * area(black.reverse()),
+        ],
+        surround_term,
+        [
+            params.castle_distance_weight
+                * castle_distance(white, white_castle, black_castle, params),
+            params.castle_distance_weight
+                * castle_distance(black, black_castle, white_castle, params),
+        ],
+        [white_cannons, black_cannons],
+        [white_aim, black_aim],
+        [
+            mobility(board, Color::White, params),
+            mobility(board, Color::Black, params),
+        ],
+        [
+            town_safety(board, Color::White, params),
+            town_safety(board, Color::Black, params),
+        ],
+    ]
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TermScore {
+    pub name: &'static str,
+    pub white: i16,
+    pub black: i16,
+}
+
+/// The contribution of every evaluation term for both colors.
+#[derive(Clone, Debug)]
+pub struct EvalTrace {
+    pub side_to_move: Color,
+    pub terms: Vec<TermScore>,
+}
+
+impl EvalTrace {
+    /// The final score from the side to move, equal to `eval_with`.
+    pub fn total(&self) -> i16 {
+        let white: i16 = self.terms.iter().map(|t| t.white - t.black).sum();
+        match self.side_to_move {
+            Color::White => white,
+            Color::Black => -white,
+        }
+    }
+}
+
+impl fmt::Display for EvalTrace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{:<16}{:>7}{:>7}{:>7}", "term", "white", "black", "diff")?;
+        for t in self.terms.iter() {
+            writeln!(
+                f,
+                "{:<16}{:>7}{:>7}{:>7}",
+                t.name,
+                t.white,
+                t.black,
+                t.white - t.black
+            )?;
+        }
+        write!(f, "total {} for {}", self.total(), self.side_to_move)
+    }
+}
+
+pub fn eval_trace(board: &Board) -> EvalTrace {
+    eval_trace_with(board, &EvalParams::default())
+}
+
+pub fn eval_trace_with(board: &Board, params: &EvalParams) -> EvalTrace {
+    let terms = color_terms(board, params);
+    EvalTrace {
+        side_to_move: board.side_to_move(),
+        terms: TERM_NAMES
+            .iter()
+            .zip(terms.iter())
+            .map(|(name, t)| TermScore {
+                name,
+                white: t[Color::White.to_index()],
+                black: t[Color::Black.to_index()],
+            })
+            .collect(),
+    }
+}
+
+/// Eval units that change the odds of winning by a factor of e.
+pub const PROBABILITY_SCALE: f64 = 10.0;
+
+/// Win probability of a score for the side it is from, `k` scales the score
+/// the way the tuner fits it to game results.
+pub fn win_probability(score: f64, k: f64) -> f64 {
+    1.0 / (1.0 + (-k * score / PROBABILITY_SCALE).exp())
 }
 
 pub fn simple_eval(board: &Board) -> i16 {
@@ -38,6 +210,7 @@ fn piece_count(mask: BitBoard) -> i16 {
     mask.count_bits() as i16
 }
 
+/// Counts the neighbours of `me` minus those of `enemy` around every piece on the board.
 #[inline]
 fn surround(me: BitBoard, enemy: BitBoard) -> i16 {
     let mut score = 0;
@@ -51,16 +224,18 @@ fn surround(me: BitBoard, enemy: BitBoard) -> i16 {
 }
 
 #[inline]
-fn castle_distance(mask: BitBoard, my_castle: Square, enemy_castle: Square) -> i16 {
+fn castle_distance(
+    mask: BitBoard,
+    my_castle: Square,
+    enemy_castle: Square,
+    params: &EvalParams,
+) -> i16 {
     // maybe give boni if near castle instead of negative if away from both
-    const CUTOFF: i16 = 3;
-    const ENEMY_IMPORTANCE: i16 = 1;
-    const MY_IMPORTANCE: i16 = 0;
     let mut score = 0;
     for sq in mask {
-        let s = (distance_square(sq, my_castle) as i16 - MY_IMPORTANCE)
-            .min(distance_square(sq, enemy_castle) as i16 - ENEMY_IMPORTANCE)
-            - CUTOFF;
+        let s = (distance_square(sq, my_castle) as i16 - params.castle_my_importance)
+            .min(distance_square(sq, enemy_castle) as i16 - params.castle_enemy_importance)
+            - params.castle_cutoff;
         if s > 0 {
             score -= s;
         }
@@ -68,6 +243,63 @@ fn castle_distance(mask: BitBoard, my_castle: Square, enemy_castle: Square) -> i
     score
 }
 
+/// Scores the cannons of `color` by orientation and what they are aiming at.
+#[inline]
+fn cannons(board: &Board, color: Color, params: &EvalParams) -> (i16, i16) {
+    let mut cannons = 0;
+    let mut aim = 0;
+    let enemy_castle = board.castle_with_color(!color);
+    visit_cannons(board, color, |cannon| {
+        cannons += match cannon.orientation {
+            Orientation::Diagonal => params.cannon_diagonal,
+            Orientation::Vertical => params.cannon_vertical,
+            Orientation::Horizontal => params.cannon_horizontal,
+        };
+        aim += params.aim_soldier
+            * (cannon.targets & board.pieces_with_color(!color)).count_bits() as i16;
+        if (cannon.targets & enemy_castle).is_not_empty() {
+            aim += params.aim_town;
+        }
+    });
+    (cannons, aim)
+}
+
+/// Number of moves `color` would have if it were to move.
+#[inline]
+fn mobility(board: &Board, color: Color, params: &EvalParams) -> i16 {
+    if params.mobility_weight == 0 {
+        return 0;
+    }
+    let moves = MoveGen::count(board, color) as i16;
+    params.mobility_weight * moves / params.mobility_divisor.max(1)
+}
+
+/// Scores the attackers, defenders and open shot lines around the town of `color`.
+#[inline]
+fn town_safety(board: &Board, color: Color, params: &EvalParams) -> i16 {
+    let castle = board.castle_with_color(color);
+    if castle.is_empty() {
+        return 0;
+    }
+    let town = castle.to_square();
+    let near = distance_ring(town, 1) | distance_ring(town, 2);
+    let attackers = (near & board.pieces_with_color(!color)).count_bits() as i16;
+    let defenders = (near & board.pieces_with_color(color)).count_bits() as i16;
+
+    // a line is open if the neighbour of the town is free and the line is long enough to shoot along
+    let unoccupied = !board.pieces_with_castles();
+    let open_lines = (0..8)
+        .filter(|&d| {
+            (shot_blocker(town, d) & unoccupied).is_not_empty()
+                && shot_targets(town, d).is_not_empty()
+        })
+        .count() as i16;
+
+    params.town_defender * defenders
+        - params.town_attacker * attackers
+        - params.town_open_line * open_lines
+}
+
 #[inline]
 fn area(mask: BitBoard) -> i16 {
     // TODO make this better by having a min area too and then weighting those against the castles positions (this is kinda covered by castle dist already)
@@ -85,3 +317,80 @@ fn area(mask: BitBoard) -> i16 {
 
     max_rank_in_file.iter().sum::<u8>() as i16
 }
+
+#[test]
+fn eval_trace_test() {
+    let params = EvalParams::default();
+    let mut board = Board::start_position();
+    let trace = eval_trace(&board);
+    assert_eq!(trace.terms.len(), NUM_TERMS);
+    assert_eq!(trace.total(), eval_with(&board, &params));
+    // the start position is symmetric
+    assert_eq!(trace.total(), 0);
+
+    for m in ["c3d4", "d6d5", "d4d5"] {
+        let m = crate::notation::parse_move(&board, m).unwrap();
+        board.apply_move(m);
+        let trace = eval_trace(&board);
+        assert_eq!(trace.total(), eval_with(&board, &params));
+    }
+    let trace = eval_trace(&board);
+    assert_eq!(trace.terms[0].white - trace.terms[0].black, 8);
+
+    // a diagonal cannon aiming at the black town
+    let board = Board::from_fen("7B/8/8/4w3/3w4/2w5/8/7W w").unwrap();
+    let trace = eval_trace(&board);
+    let term = |name| trace.terms.iter().find(|t| t.name == name).unwrap();
+    assert_eq!(term("cannons").white, params.cannon_diagonal);
+    assert_eq!(term("aim").white, params.aim_town);
+    assert_eq!(term("cannons").black, 0);
+    assert!(term("mobility").white > 0);
+    assert_eq!(term("mobility").black, 0);
+
+    // soldiers next to the black town make it less safe
+    let board = Board::from_fen("6B1/6w1/8/8/8/8/8/7W b").unwrap();
+    let trace = eval_trace(&board);
+    let safety = trace
+        .terms
+        .iter()
+        .find(|t| t.name == "town_safety")
+        .unwrap();
+    assert!(safety.black < safety.white);
+}
+
+#[test]
+fn classic_eval_baseline_test() {
+    // without the terms added later the classic eval scores exactly like the original one
+    let params = EvalParams {
+        cannon_diagonal: 0,
+        cannon_vertical: 0,
+        cannon_horizontal: 0,
+        aim_soldier: 0,
+        aim_town: 0,
+        mobility_weight: 0,
+        town_attacker: 0,
+        town_defender: 0,
+        town_open_line: 0,
+        ..EvalParams::default()
+    };
+    for (moves, expected) in [
+        ("", 0),
+        ("c3d4", -4),
+        ("c3d4 d6d5", 2),
+        ("c3d4 d6d5 d4d5", -16),
+        ("c3d4 d6d5 d4d5 f6e5 e3f4 e5f4", 1),
+        ("a3b4 h6g5 g3h4 b6c5 b4c5 d6c5", -4),
+    ] {
+        let mut board = Board::start_position();
+        for m in moves.split_whitespace() {
+            board.apply_move(crate::notation::parse_move(&board, m).unwrap());
+        }
+        assert_eq!(eval_with(&board, &params), expected, "{}", moves);
+        assert_eq!(
+            eval_trace_with(&board, &params).total(),
+            expected,
+            "{}",
+            moves
+        );
+    }
+}
@@ -0,0 +1,139 @@
+use crate::bitboard::{BitBoard, EMPTY};
+use crate::board::Board;
+use crate::color::Color;
+use crate::square::Square;
+use crate::tables::*;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Orientation {
+    Horizontal,
+    Vertical,
+    Diagonal,
+}
+
+impl Orientation {
+    pub fn is_diagonal(self) -> bool {
+        self == Orientation::Diagonal
+    }
+
+    pub fn is_orthogonal(self) -> bool {
+        !self.is_diagonal()
+    }
+}
+
+/// Three soldiers of the same color next to each other in a line.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Cannon {
+    /// The squares of the cannon from one end to the other
+    pub squares: [Square; 3],
+    pub orientation: Orientation,
+    /// Empty squares either end of the cannon can slide to
+    pub slides: BitBoard,
+    /// Squares the cannon can shoot because the square in front of its head is empty
+    pub targets: BitBoard,
+}
+
+impl Cannon {
+    pub fn mask(&self) -> BitBoard {
+        self.squares
+            .iter()
+            .fold(EMPTY, |b, sq| b | BitBoard::from_square(*sq))
+    }
+
+    /// Shot targets which are occupied by enemy soldiers or the enemy town.
+    pub fn aimed_at(&self, board: &Board, color: Color) -> BitBoard {
+        self.targets & (board.pieces_with_color(!color) | board.castle_with_color(!color))
+    }
+}
+
+/// Finds every cannon of `color`, each formation is only returned once.
+pub fn cannons(board: &Board, color: Color) -> Vec<Cannon> {
+    let mut cannons = Vec::new();
+    visit_cannons(board, color, |c| cannons.push(c));
+    cannons
+}
+
+/// Calls `f` for every cannon of `color` without collecting them.
+pub fn visit_cannons<F: FnMut(Cannon)>(board: &Board, color: Color, mut f: F) {
+    let my_pieces = board.pieces_with_color(color);
+    let unoccupied = !board.pieces_with_castles();
+
+    for start in my_pieces {
+        // only look at ends with a higher index to not find every cannon twice
+        let ends = distance_dir_ring(start, 2) & my_pieces;
+        for end in ends.filter(|end| end.0 > start.0) {
+            let middle = between(start, end);
+            if (middle & my_pieces) != middle {
+                continue;
+            }
+
+            let orientation = if start.rank_index() == end.rank_index() {
+                Orientation::Horizontal
+            } else if start.file_index() == end.file_index() {
+                Orientation::Vertical
+            } else {
+                Orientation::Diagonal
+            };
+
+            let mut slides = EMPTY;
+            let mut targets = EMPTY;
+            for (head, tail) in [(start, end), (end, start)] {
+                let body = middle | BitBoard::from_square(tail);
+                // the tail jumps over the rest of the cannon
+                for dst in distance_dir_ring(tail, 3) & unoccupied {
+                    if between(tail, dst) == middle | BitBoard::from_square(head) {
+                        slides |= BitBoard::from_square(dst);
+                    }
+                }
+                for d in 0..8 {
+                    if shot_body(head, d) == body
+                        && (shot_blocker(head, d) & unoccupied).is_not_empty()
+                    {
+                        targets |= shot_targets(head, d);
+                    }
+                }
+            }
+
+            f(Cannon {
+                squares: [start, middle.to_square(), end],
+                orientation,
+                slides,
+                targets,
+            });
+        }
+    }
+}
+
+#[test]
+fn cannons_test() {
+    let board = Board::start_position();
+    let white = cannons(&board, Color::White);
+    assert_eq!(white.len(), 4);
+    assert!(white.iter().all(|c| c.orientation == Orientation::Vertical));
+    let a_file = white.iter().find(|c| c.squares[0] == Square::A1).unwrap();
+    assert_eq!(a_file.squares, [Square::A1, Square::A2, Square::A3]);
+    assert_eq!(a_file.slides, BitBoard::from_square(Square::A4));
+    assert_eq!(
+        a_file.targets,
+        BitBoard::from_square(Square::A5) | BitBoard::from_square(Square::A6)
+    );
+
+    let mut board = Board::new(Color::White);
+    board.set_castle(Color::White, Square::H1);
+    board.set_castle(Color::Black, Square::A8);
+    board.set(Color::White, Square::C3);
+    board.set(Color::White, Square::D4);
+    board.set(Color::White, Square::E5);
+    board.set(Color::White, Square::F6);
+    board.set(Color::Black, Square::H8);
+    let white = cannons(&board, Color::White);
+    assert_eq!(white.len(), 2);
+    assert!(white.iter().all(|c| c.orientation.is_diagonal()));
+    let upper = white.iter().find(|c| c.squares[2] == Square::F6).unwrap();
+    // the upper cannon can not slide backwards onto its own soldier but it can shoot at h8
+    assert_eq!(upper.slides, BitBoard::from_square(Square::G7));
+    assert_eq!(
+        upper.aimed_at(&board, Color::White),
+        BitBoard::from_square(Square::H8)
+    );
+}
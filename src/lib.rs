@@ -1,18 +1,36 @@
-#![feature(generic_const_exprs)]
-#![feature(const_for)]
-#![feature(int_abs_diff)]
-
 #[macro_use]
 pub mod macros;
+#[macro_use]
+pub mod params;
+pub mod attacks;
 pub mod bitboard;
 pub mod bits;
 pub mod board;
+pub mod book;
 pub mod cannon_move;
 pub mod color;
 pub mod defs;
+pub mod engine_match;
 pub mod eval;
+pub mod formation;
+pub mod mcts;
+pub mod move_picker;
 pub mod movegen;
+#[cfg(feature = "nnue")]
+pub mod nnue;
+pub mod notation;
+pub mod player;
+pub mod proof;
+pub mod protocol;
+pub mod record;
+pub mod rng;
 pub mod search;
+pub mod see;
+pub mod selfplay;
+pub mod skill;
 pub mod square;
+pub mod tablebase;
 pub mod tables;
+pub mod tournament;
 pub mod transposition;
+pub mod tuner;
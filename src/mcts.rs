@@ -0,0 +1,314 @@
+use crate::board::Board;
+use crate::cannon_move::{BitMove, MoveWithScore};
+use crate::eval::{eval_with, win_probability, EvalParams, PROBABILITY_SCALE};
+use crate::rng::Rng;
+use crate::search::{SearchLimits, WIN};
+use std::sync::Mutex;
+use std::time::Instant;
+
+const NO_PARENT: usize = usize::MAX;
+
+define_params! {
+    /// Constants of the Monte Carlo tree search.
+    MctsParams {
+        /// UCT exploration constant in hundredths
+        exploration: i32 = 141,
+        /// Random moves of a playout before the position is evaluated
+        playout_depth: u8 = 16,
+        /// Playouts per ply when the search is only limited by depth
+        iterations_per_depth: u32 = 2000,
+    }
+}
+
+/// How a new leaf is scored.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Playout {
+    /// Random moves until the game ends or the playout depth is reached, then the eval
+    Random,
+    /// The eval of the leaf itself
+    Eval,
+}
+
+#[derive(Clone)]
+struct Node {
+    mv: BitMove,
+    parent: usize,
+    /// Children are stored next to each other, starting at `first_child`
+    first_child: usize,
+    num_children: usize,
+    expanded: bool,
+    /// Game result if the position is decided, from the player who moved into it
+    terminal: Option<f64>,
+    visits: u32,
+    virtual_loss: u32,
+    /// Sum of the results from the player who moved into this node
+    value: f64,
+}
+
+impl Node {
+    fn new(mv: BitMove, parent: usize, terminal: Option<f64>) -> Self {
+        Self {
+            mv,
+            parent,
+            first_child: 0,
+            num_children: 0,
+            expanded: false,
+            terminal,
+            visits: 0,
+            virtual_loss: 0,
+            value: 0.0,
+        }
+    }
+}
+
+/// Inverse of `win_probability`, so the result can be compared to alpha beta scores.
+fn probability_score(p: f64) -> i16 {
+    if p >= 1.0 {
+        return WIN;
+    }
+    if p <= 0.0 {
+        return -WIN;
+    }
+    (-PROBABILITY_SCALE * (1.0 / p - 1.0).ln()).clamp(-(WIN - 1) as f64, (WIN - 1) as f64) as i16
+}
+
+/// UCT search over `Board::generate_moves`, an alternative to the alpha beta `Searcher`.
+/// Several threads share the tree and keep each other apart with virtual losses.
+pub struct MctsSearcher {
+    pub params: MctsParams,
+    pub eval_params: EvalParams,
+    pub playout: Playout,
+    pub threads: usize,
+    pub seed: u64,
+    root_visits: Vec<(BitMove, u32)>,
+}
+
+impl Default for MctsSearcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MctsSearcher {
+    pub fn new() -> Self {
+        Self {
+            params: MctsParams::default(),
+            eval_params: EvalParams::default(),
+            playout: Playout::Random,
+            threads: 1,
+            seed: 0,
+            root_visits: Vec::new(),
+        }
+    }
+
+    /// Forgets the root visits of the last search.
+    pub fn clear(&mut self) {
+        self.root_visits.clear();
+    }
+
+    /// The root moves with their visit counts of the last search, most visited first.
+    pub fn root_visits(&self) -> &[(BitMove, u32)] {
+        &self.root_visits
+    }
+
+    pub fn search(&mut self, board: &mut Board, max_depth: u8) -> MoveWithScore {
+        self.search_with_limits(board, &SearchLimits::depth(max_depth))
+    }
+
+    /// Runs playouts until the node or time limit is used up, every playout counts as one node.
+    /// Without either limit `iterations_per_depth` playouts are run for every ply of depth.
+    pub fn search_with_limits(
+        &mut self,
+        board: &mut Board,
+        limits: &SearchLimits,
+    ) -> MoveWithScore {
+        let iterations = match (limits.nodes, limits.time) {
+            (Some(nodes), _) => nodes.max(1),
+            (None, Some(_)) => u64::MAX,
+            (None, None) => (limits.depth as u64 * self.params.iterations_per_depth as u64).max(1),
+        };
+        let start = Instant::now();
+        let tree = Mutex::new(vec![Node::new(BitMove::null(), NO_PARENT, None)]);
+        let threads = self.threads.max(1) as u64;
+
+        std::thread::scope(|scope| {
+            for thread in 0..threads {
+                let tree = &tree;
+                let root = board.shallow_clone();
+                // spread the iterations so they add up to exactly the limit
+                let count = iterations / threads + (thread < iterations % threads) as u64;
+                let mut rng = Rng::new(self.seed ^ thread);
+                let this = &*self;
+                scope.spawn(move || {
+                    for _ in 0..count {
+                        // the first playout always runs so there is a move to return
+                        if limits.time.is_some_and(|time| start.elapsed() >= time)
+                            && tree.lock().unwrap()[0].visits > 0
+                        {
+                            break;
+                        }
+                        this.iteration(tree, &root, &mut rng);
+                    }
+                });
+            }
+        });
+
+        let tree = tree.into_inner().unwrap();
+        let root = &tree[0];
+        let children = &tree[root.first_child..root.first_child + root.num_children];
+        let mut visits: Vec<(BitMove, u32)> = children.iter().map(|c| (c.mv, c.visits)).collect();
+        visits.sort_by_key(|(_, v)| std::cmp::Reverse(*v));
+        self.root_visits = visits;
+
+        match children.iter().max_by_key(|c| c.visits) {
+            Some(best) => {
+                let score = match best.terminal {
+                    Some(result) => probability_score(result),
+                    None => probability_score(best.value / best.visits.max(1) as f64),
+                };
+                MoveWithScore::new_with_score(best.mv, score)
+            }
+            None => MoveWithScore::new_with_score(BitMove::null(), -WIN),
+        }
+    }
+
+    /// One selection, expansion, playout and backpropagation. The tree is only locked while
+    /// walking it, the playout runs without the lock.
+    fn iteration(&self, tree: &Mutex<Vec<Node>>, root: &Board, rng: &mut Rng) {
+        let mut board = root.shallow_clone();
+        let (leaf, result) = {
+            let mut tree = tree.lock().unwrap();
+            let mut node = 0;
+            tree[node].virtual_loss += 1;
+            loop {
+                if tree[node].terminal.is_some() {
+                    break;
+                }
+                if !tree[node].expanded {
+                    // a new leaf is played out first, its children are chosen on later visits
+                    self.expand(&mut tree, node, &board);
+                    break;
+                }
+                node = self.select(&tree, node);
+                tree[node].virtual_loss += 1;
+                board.apply_move(tree[node].mv);
+            }
+            (node, tree[node].terminal)
+        };
+
+        // from the player who moved into the leaf
+        let result = match result {
+            Some(result) => result,
+            None => 1.0 - self.playout(&mut board, rng),
+        };
+
+        let mut tree = tree.lock().unwrap();
+        let mut node = leaf;
+        let mut result = result;
+        while node != NO_PARENT {
+            let n = &mut tree[node];
+            n.virtual_loss -= 1;
+            n.visits += 1;
+            n.value += result;
+            result = 1.0 - result;
+            node = n.parent;
+        }
+    }
+
+    fn expand(&self, tree: &mut Vec<Node>, node: usize, board: &Board) {
+        let moves = board.generate_moves();
+        if moves.is_empty() {
+            // the side to move has lost, so the player who moved here has won
+            tree[node].terminal = Some(1.0);
+        }
+        tree[node].first_child = tree.len();
+        tree[node].num_children = moves.len();
+        tree[node].expanded = true;
+        for m in moves {
+            let terminal = board.captures_town(m).then_some(1.0);
+            tree.push(Node::new(m, node, terminal));
+        }
+    }
+
+    fn select(&self, tree: &[Node], node: usize) -> usize {
+        let parent = &tree[node];
+        let exploration = self.params.exploration as f64 / 100.0;
+        let parent_visits = ((parent.visits + parent.virtual_loss) as f64).max(1.0).ln();
+        let mut best = parent.first_child;
+        let mut best_value = f64::NEG_INFINITY;
+        let children = &tree[parent.first_child..parent.first_child + parent.num_children];
+        for (i, c) in children.iter().enumerate() {
+            // a virtual loss counts as a visit without any reward
+            let visits = (c.visits + c.virtual_loss) as f64;
+            let value = if let Some(result) = c.terminal {
+                result * 1e6
+            } else if visits == 0.0 {
+                f64::INFINITY
+            } else {
+                c.value / visits + exploration * (parent_visits / visits).sqrt()
+            };
+            if value > best_value {
+                best = parent.first_child + i;
+                best_value = value;
+            }
+        }
+        best
+    }
+
+    /// The expected result for the side to move of `board`.
+    fn playout(&self, board: &mut Board, rng: &mut Rng) -> f64 {
+        if self.playout == Playout::Random {
+            let side = board.side_to_move();
+            for _ in 0..self.params.playout_depth {
+                let moves = board.generate_moves();
+                if moves.is_empty() {
+                    return if board.side_to_move() == side {
+                        0.0
+                    } else {
+                        1.0
+                    };
+                }
+                let m = moves.get(rng.below(moves.len()));
+                if board.captures_town(m) {
+                    return if board.side_to_move() == side {
+                        1.0
+                    } else {
+                        0.0
+                    };
+                }
+                board.apply_move(m);
+            }
+            let p = win_probability(eval_with(board, &self.eval_params) as f64, 1.0);
+            return if board.side_to_move() == side {
+                p
+            } else {
+                1.0 - p
+            };
+        }
+        win_probability(eval_with(board, &self.eval_params) as f64, 1.0)
+    }
+}
+
+#[test]
+fn mcts_test() {
+    use crate::notation::{move_to_string, parse_move};
+
+    // white can take the black town right away
+    let mut board = Board::from_fen("B7/w7/8/8/8/8/8/7W w").unwrap();
+    let mut searcher = MctsSearcher::new();
+    let m = searcher.search_with_limits(&mut board, &SearchLimits::nodes(200));
+    assert_eq!(move_to_string(m.bitmove()), "a7a8");
+    assert_eq!(m.score(), WIN);
+
+    let mut board = Board::start_position();
+    board.apply_move(parse_move(&board, "c3d4").unwrap());
+    searcher.threads = 4;
+    searcher.playout = Playout::Eval;
+    let m = searcher.search_with_limits(&mut board, &SearchLimits::nodes(1000));
+    assert!(board.generate_moves().contains(m.bitmove()));
+    let visits = searcher.root_visits();
+    assert_eq!(visits.len(), board.generate_moves().len());
+    assert_eq!(visits[0].0, m.bitmove());
+    // the root itself is visited once by the first playout
+    assert_eq!(visits.iter().map(|(_, v)| *v as u64).sum::<u64>(), 999);
+}
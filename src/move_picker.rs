@@ -0,0 +1,174 @@
+use crate::board::Board;
+use crate::cannon_move::BitMove;
+use crate::movegen::{MoveGen, MoveList};
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Stage {
+    TTMove,
+    GenerateCaptures,
+    Captures,
+    Killers,
+    GenerateQuiets,
+    Quiets,
+    GenerateRetreats,
+    Retreats,
+    Placements,
+    Done,
+}
+
+/// Yields the moves of a position in stages so that later stages are only generated
+/// if no cutoff happened before: TT move, captures and shots, killers, quiet moves and retreats.
+pub struct MovePicker {
+    stage: Stage,
+    tt_move: BitMove,
+    killers: [BitMove; 2],
+    killer_idx: usize,
+    moves: MoveList,
+    captures_only: bool,
+}
+
+impl MovePicker {
+    pub fn new(board: &Board, tt_move: BitMove, killers: [BitMove; 2]) -> Self {
+        let (stage, moves) = if board.castle_with_color(board.side_to_move()).is_empty() {
+            (Stage::Placements, MoveGen::generate_placement_moves(board))
+        } else {
+            (Stage::TTMove, MoveList::default())
+        };
+        Self {
+            stage,
+            tt_move: if MoveGen::is_pseudo_legal(board, tt_move) {
+                tt_move
+            } else {
+                BitMove::null()
+            },
+            killers,
+            killer_idx: 0,
+            moves,
+            captures_only: false,
+        }
+    }
+
+    /// Picker for quiescence search that only yields captures and shots.
+    pub fn new_captures(board: &Board) -> Self {
+        let mut picker = Self::new(board, BitMove::null(), [BitMove::null(); 2]);
+        if picker.stage == Stage::TTMove {
+            picker.stage = Stage::GenerateCaptures;
+        }
+        picker.captures_only = true;
+        picker
+    }
+
+    fn is_special(&self, m: BitMove) -> bool {
+        m == self.tt_move || self.killers.contains(&m)
+    }
+
+    /// Returns the next move, `board` has to be in the same position as when the picker was created.
+    pub fn next(&mut self, board: &Board) -> Option<BitMove> {
+        loop {
+            match self.stage {
+                Stage::TTMove => {
+                    self.stage = Stage::GenerateCaptures;
+                    if self.tt_move != BitMove::null() {
+                        return Some(self.tt_move);
+                    }
+                }
+                Stage::GenerateCaptures => {
+                    self.moves = MoveGen::generate_captures(board);
+                    self.stage = Stage::Captures;
+                }
+                Stage::Captures => match self.moves.next() {
+                    Some(m) if m == self.tt_move => continue,
+                    Some(m) => return Some(m),
+                    None if self.captures_only => self.stage = Stage::Done,
+                    None => self.stage = Stage::Killers,
+                },
+                Stage::Killers => {
+                    if self.killer_idx >= self.killers.len() {
+                        self.stage = Stage::GenerateQuiets;
+                        continue;
+                    }
+                    let killer = self.killers[self.killer_idx];
+                    self.killer_idx += 1;
+                    if killer != self.tt_move
+                        && !killer.is_capture()
+                        && MoveGen::is_pseudo_legal(board, killer)
+                    {
+                        return Some(killer);
+                    }
+                }
+                Stage::GenerateQuiets => {
+                    self.moves = MoveGen::generate_quiets(board);
+                    self.stage = Stage::Quiets;
+                }
+                Stage::Quiets => match self.moves.next() {
+                    Some(m) if self.is_special(m) => continue,
+                    Some(m) => return Some(m),
+                    None => self.stage = Stage::GenerateRetreats,
+                },
+                Stage::GenerateRetreats => {
+                    self.moves = MoveGen::generate_retreat_moves(board);
+                    self.stage = Stage::Retreats;
+                }
+                Stage::Retreats => match self.moves.next() {
+                    Some(m) if self.is_special(m) => continue,
+                    Some(m) => return Some(m),
+                    None => self.stage = Stage::Done,
+                },
+                Stage::Placements => match self.moves.next() {
+                    Some(m) => return Some(m),
+                    None => self.stage = Stage::Done,
+                },
+                Stage::Done => return None,
+            }
+        }
+    }
+}
+
+#[test]
+fn move_picker_test() {
+    use crate::color::Color;
+    use crate::square::Square;
+
+    let mut board = Board::start_position();
+    for m in [
+        BitMove::make(Square::C3, Square::D4),
+        BitMove::make(Square::D6, Square::D5),
+        BitMove::make(Square::E3, Square::E4),
+    ] {
+        let m = board
+            .generate_moves()
+            .find(|g| g.src() == m.src() && g.dst() == m.dst());
+        board.apply_move(m.unwrap());
+    }
+    assert_eq!(board.side_to_move(), Color::Black);
+
+    let all: Vec<BitMove> = board.generate_moves().collect();
+    let tt_move = *all.last().unwrap();
+    let killer = all
+        .iter()
+        .copied()
+        .find(|m| !m.is_capture() && *m != tt_move)
+        .unwrap();
+    let mut picker = MovePicker::new(
+        &board,
+        tt_move,
+        [killer, BitMove::make(Square::A1, Square::A2)],
+    );
+    let mut picked = Vec::new();
+    while let Some(m) = picker.next(&board) {
+        picked.push(m);
+    }
+    assert_eq!(picked[0], tt_move);
+    assert_eq!(picked.len(), all.len());
+    assert!(all.iter().all(|m| picked.contains(m)));
+    let captures = all.iter().filter(|m| m.is_capture()).count();
+    assert!(picked[1..=captures].iter().all(|m| m.is_capture()));
+    assert_eq!(picked[captures + 1], killer);
+
+    let mut picker = MovePicker::new_captures(&board);
+    let mut picked = Vec::new();
+    while let Some(m) = picker.next(&board) {
+        picked.push(m);
+    }
+    assert_eq!(picked.len(), captures);
+}
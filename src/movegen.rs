@@ -1,13 +1,17 @@
 use crate::bitboard::BitBoard;
+use crate::bitboard::EMPTY;
 use crate::board::Board;
-use crate::cannon_move::BitMove;
-use crate::color::*;
+use crate::cannon_move::{BitMove, MoveKind};
+use crate::color::Color;
+use crate::defs::{Rank, ALL_FILES};
 use crate::square::Square;
 use crate::tables::*;
 
-pub const MAX_MOVES: usize = 62;
+/// Number of moves stored inline, positions with more moves spill into `overflow`.
+pub const MAX_MOVES: usize = 128;
 pub struct MoveList {
     pub moves: [BitMove; MAX_MOVES],
+    pub overflow: Vec<BitMove>,
     pub len: usize,
     pub idx: usize,
 }
@@ -16,6 +20,7 @@ impl Default for MoveList {
     fn default() -> Self {
         Self {
             moves: [BitMove::null(); MAX_MOVES],
+            overflow: Vec::new(),
             len: 0,
             idx: 0,
         }
@@ -26,11 +31,25 @@ impl MoveList {
     pub fn push(&mut self, mv: BitMove) {
         if self.len < MAX_MOVES {
             self.moves[self.len] = mv;
-            self.len += 1;
         } else {
-            println!("overflow");
+            self.overflow.push(mv);
         }
+        self.len += 1;
     }
+
+    pub fn get(&self, idx: usize) -> BitMove {
+        assert!(idx < self.len);
+        if idx < MAX_MOVES {
+            self.moves[idx]
+        } else {
+            self.overflow[idx - MAX_MOVES]
+        }
+    }
+
+    pub fn contains(&self, mv: BitMove) -> bool {
+        (0..self.len).any(|i| self.get(i) == mv)
+    }
+
     pub fn len(&self) -> usize {
         self.len
     }
@@ -42,7 +61,7 @@ impl MoveList {
 
 impl ExactSizeIterator for MoveList {
     fn len(&self) -> usize {
-        self.len()
+        self.len - self.idx
     }
 }
 
@@ -50,7 +69,7 @@ impl Iterator for MoveList {
     type Item = BitMove;
 
     fn size_hint(&self) -> (usize, Option<usize>) {
-        let len = self.len;
+        let len = self.len - self.idx;
         (len, Some(len))
     }
 
@@ -58,7 +77,7 @@ impl Iterator for MoveList {
         if self.idx >= self.len {
             None
         } else {
-            let m = self.moves[self.idx];
+            let m = self.get(self.idx);
             self.idx += 1;
             Some(m)
         }
@@ -82,36 +101,219 @@ impl MoveGen {
     pub fn generate(board: &Board) -> MoveList {
         let mut move_list = MoveList::default();
 
+        // nothing else can be played until the town is placed
+        if board.castle_with_color(board.side_to_move()).is_empty() {
+            MoveGen::generate_placements(&mut move_list, board);
+            return move_list;
+        }
+
         // order is important (captures first)
-        MoveGen::generate_side_moves(&mut move_list, &board);
-        MoveGen::generate_cannon_shots(&mut move_list, &board);
-        MoveGen::generate_cannon_jumps(&mut move_list, &board);
+        MoveGen::generate_side_moves(&mut move_list, board);
+        MoveGen::generate_cannon_shots(&mut move_list, board);
+        MoveGen::generate_cannon_jumps(&mut move_list, board);
         // TODO putting retreats above forwards results in endless loop even in winning position (maybe just fixed with repition rule)
-        MoveGen::generate_forward_moves(&mut move_list, &board);
-        MoveGen::generate_retreats(&mut move_list, &board);
+        MoveGen::generate_forward_moves(&mut move_list, board);
+        MoveGen::generate_retreats(&mut move_list, board);
         move_list
     }
 
     pub fn generate_captures(board: &Board) -> MoveList {
         let mut move_list = MoveList::default();
         // We know that retreats and jumps can never be captures
-        MoveGen::generate_side_moves(&mut move_list, &board);
-        MoveGen::generate_cannon_shots(&mut move_list, &board);
-        MoveGen::generate_forward_moves(&mut move_list, &board);
-        let enemies = board.enemy_pieces() | board.enemy_castle();
-        let is_capture = |m: &BitMove| (BitBoard::from_square(m.dst()) & enemies).is_not_empty();
-        move_list.filter(move |m| is_capture(m)).collect()
+        MoveGen::generate_side_moves(&mut move_list, board);
+        MoveGen::generate_cannon_shots(&mut move_list, board);
+        MoveGen::generate_forward_moves(&mut move_list, board);
+        move_list.filter(|m| m.is_capture()).collect()
+    }
+
+    /// Forward steps and cannon moves, which never capture.
+    pub fn generate_quiets(board: &Board) -> MoveList {
+        let mut move_list = MoveList::default();
+        MoveGen::generate_cannon_jumps(&mut move_list, board);
+        MoveGen::generate_forward_moves(&mut move_list, board);
+        move_list.filter(|m| !m.is_capture()).collect()
+    }
+
+    pub fn generate_retreat_moves(board: &Board) -> MoveList {
+        let mut move_list = MoveList::default();
+        MoveGen::generate_retreats(&mut move_list, board);
+        move_list
+    }
+
+    pub fn generate_placement_moves(board: &Board) -> MoveList {
+        let mut move_list = MoveList::default();
+        MoveGen::generate_placements(&mut move_list, board);
+        move_list
+    }
+
+    /// Number of moves `color` has, counted on the bitboards without building a move list.
+    /// Equal to the length of `generate` with `color` to move.
+    pub fn count(board: &Board, color: Color) -> u32 {
+        let mine = board.pieces_with_color(color);
+        let enemy_pieces = board.pieces_with_color(!color);
+        let enemy = enemy_pieces | board.castle_with_color(!color);
+        let unoccupied = !board.pieces_with_castles();
+        if board.castle_with_color(color).is_empty() {
+            let home = match color {
+                Color::White => Rank::One,
+                Color::Black => Rank::Eight,
+            };
+            return ALL_FILES
+                .iter()
+                .filter(|f| {
+                    (unoccupied & BitBoard::from_square(Square::make_square(home, **f)))
+                        .is_not_empty()
+                })
+                .count() as u32;
+        }
+
+        let mut count = 0;
+        for src in mine {
+            count += (front(color, src) & !mine).count_bits();
+            count += (sides(color, src) & enemy).count_bits();
+            if ((front(color, src) | sides(color, src)) & enemy_pieces).is_not_empty() {
+                for dst in retreats(color, src) & unoccupied {
+                    count += (between(src, dst) & unoccupied).is_not_empty() as u32;
+                }
+            }
+            // cannons need a neighbour of the same color
+            if (distance_ring(src, 1) & mine).is_empty() {
+                continue;
+            }
+            for dst in distance_ring(src, 3) & unoccupied {
+                let between = between(src, dst);
+                count += (between.is_not_empty() && (between & mine) == between) as u32;
+            }
+            for d in 0..8 {
+                let body = shot_body(src, d);
+                if (shot_blocker(src, d) & unoccupied).is_not_empty()
+                    && body.is_not_empty()
+                    && (body & mine) == body
+                {
+                    count += (shot_targets(src, d) & enemy).count_bits();
+                }
+            }
+        }
+        count
+    }
+
+    /// Moves without a capture the side that is not to move could have played to reach `board`,
+    /// used for retrograde analysis. Every move is checked against the position before it.
+    pub fn generate_reverse_moves(board: &Board) -> Vec<(BitMove, Board)> {
+        let mover = !board.side_to_move();
+        let unoccupied = !board.pieces_with_castles();
+        let castles = [
+            board.castle_with_color(Color::White),
+            board.castle_with_color(Color::Black),
+        ];
+        let mut moves = Vec::new();
+        if castles[mover.to_index()].is_empty() {
+            return moves;
+        }
+        for dst in board.pieces_with_color(mover) {
+            // steps, retreats and cannon moves cover at most three squares
+            let sources = (distance_ring(dst, 1) | distance_ring(dst, 2) | distance_ring(dst, 3))
+                & unoccupied;
+            for src in sources {
+                let mut soldiers = [
+                    board.pieces_with_color(Color::White),
+                    board.pieces_with_color(Color::Black),
+                ];
+                soldiers[mover.to_index()] ^=
+                    BitBoard::from_square(src) | BitBoard::from_square(dst);
+                let before = Board::from_bitboards(mover, soldiers, castles);
+                for kind in [MoveKind::Step, MoveKind::Retreat, MoveKind::CannonMove] {
+                    let m = BitMove::make_with_kind(src, dst, kind);
+                    if MoveGen::is_pseudo_legal(&before, m) {
+                        moves.push((m, before.shallow_clone()));
+                    }
+                }
+            }
+        }
+        moves
+    }
+
+    /// Checks if `m` is one of the moves `generate` would return for `board`
+    /// without generating all of them, used to validate TT moves and killers.
+    pub fn is_pseudo_legal(board: &Board, m: BitMove) -> bool {
+        if m == BitMove::null() {
+            return false;
+        }
+        let color = board.side_to_move();
+        if board.castle_with_color(color).is_empty() || m.is_placement() {
+            return m.is_placement() && MoveGen::generate_placement_moves(board).contains(m);
+        }
+        let src = m.src();
+        let dst_bb = BitBoard::from_square(m.dst());
+        let my_pieces = board.player_pieces();
+        if (BitBoard::from_square(src) & my_pieces).is_empty() {
+            return false;
+        }
+        let enemy_pieces = board.enemy_pieces() | board.enemy_castle();
+        let unoccupied = !board.pieces_with_castles();
+
+        match m.kind() {
+            MoveKind::Step => {
+                (front(color, src) & dst_bb & !my_pieces & !enemy_pieces).is_not_empty()
+            }
+            MoveKind::Capture => {
+                ((front(color, src) | sides(color, src)) & dst_bb & enemy_pieces).is_not_empty()
+            }
+            MoveKind::Retreat => {
+                let adj = front(color, src) | sides(color, src);
+                (adj & board.enemy_pieces()).is_not_empty()
+                    && (retreats(color, src) & unoccupied & dst_bb).is_not_empty()
+                    && (between(src, m.dst()) & unoccupied).is_not_empty()
+            }
+            MoveKind::CannonMove => {
+                let between = between(src, m.dst());
+                (distance_ring(src, 3) & unoccupied & dst_bb).is_not_empty()
+                    && between.is_not_empty()
+                    && (between & my_pieces) == between
+            }
+            MoveKind::Shot => {
+                (dst_bb & enemy_pieces).is_not_empty()
+                    && (0..8).any(|d| {
+                        (shot_targets(src, d) & dst_bb).is_not_empty()
+                            && (shot_blocker(src, d) & unoccupied).is_not_empty()
+                            && shot_body(src, d).is_not_empty()
+                            && (shot_body(src, d) & my_pieces) == shot_body(src, d)
+                    })
+            }
+            MoveKind::Placement => unreachable!(),
+        }
+    }
+
+    #[inline]
+    fn generate_placements(moves: &mut MoveList, board: &Board) {
+        let home_rank = match board.side_to_move() {
+            Color::White => Rank::One,
+            Color::Black => Rank::Eight,
+        };
+        // any free square of the home rank, like the corners of the start position
+        for file in ALL_FILES.iter() {
+            let sq = Square::make_square(home_rank, *file);
+            if (board.pieces_with_castles() & BitBoard::from_square(sq)) == EMPTY {
+                moves.push(BitMove::make_placement(sq));
+            }
+        }
     }
 
     #[inline]
     fn generate_forward_moves(moves: &mut MoveList, board: &Board) {
         let my_pieces = board.player_pieces();
         let unoccupied = !my_pieces;
+        let enemy_pieces = board.enemy_pieces() | board.enemy_castle();
         for src in my_pieces {
             let forwards = front(board.side_to_move(), src);
             let forwards = forwards & unoccupied;
             for dst in forwards {
-                moves.push(BitMove::make(src, dst));
+                let kind = if (BitBoard::from_square(dst) & enemy_pieces).is_not_empty() {
+                    MoveKind::Capture
+                } else {
+                    MoveKind::Step
+                };
+                moves.push(BitMove::make_with_kind(src, dst, kind));
             }
         }
     }
@@ -126,7 +328,7 @@ impl MoveGen {
             let sides = sides & enemy_pieces;
 
             for dst in sides {
-                moves.push(BitMove::make(src, dst));
+                moves.push(BitMove::make_with_kind(src, dst, MoveKind::Capture));
             }
         }
     }
@@ -146,7 +348,7 @@ impl MoveGen {
                 let retreats = retreats(my_color, src) & unoccupied;
                 for dst in retreats {
                     if (between(src, dst) & unoccupied).is_not_empty() {
-                        moves.push(BitMove::make(src, dst));
+                        moves.push(BitMove::make_with_kind(src, dst, MoveKind::Retreat));
                     }
                 }
             }
@@ -164,7 +366,7 @@ impl MoveGen {
             for dst in possible_jumps {
                 let between = between(src, dst);
                 if between.is_not_empty() && (between & my_pieces_original) == between {
-                    moves.push(BitMove::make(src, dst));
+                    moves.push(BitMove::make_with_kind(src, dst, MoveKind::CannonMove));
                 }
             }
         }
@@ -179,14 +381,13 @@ impl MoveGen {
 
         for src in my_pieces {
             for d in 0..8 {
-                if (shot_blocker(src, d) & unoccupied).is_not_empty() {
-                    if shot_body(src, d).is_not_empty()
-                        && (shot_body(src, d) & my_pieces_original) == shot_body(src, d)
-                    {
-                        let shots = shot_targets(src, d) & enemy_pieces;
-                        for dst in shots {
-                            moves.push(BitMove::make_shot(src, dst));
-                        }
+                if (shot_blocker(src, d) & unoccupied).is_not_empty()
+                    && shot_body(src, d).is_not_empty()
+                    && (shot_body(src, d) & my_pieces_original) == shot_body(src, d)
+                {
+                    let shots = shot_targets(src, d) & enemy_pieces;
+                    for dst in shots {
+                        moves.push(BitMove::make_shot(src, dst));
                     }
                 }
             }
@@ -196,9 +397,7 @@ impl MoveGen {
 
 #[test]
 fn move_test() {
-    init();
     let board = Board::start_position();
-    let rev = board.pieces().reverse();
     println!("{}", board.pieces_with_castles());
     let moves = board.generate_moves_for(Square::A1);
     //let moves = board.generate_moves();
@@ -206,3 +405,143 @@ fn move_test() {
         println!("{} -> {}", m.src(), m.dst());
     }
 }
+
+#[test]
+fn count_test() {
+    use crate::rng::Rng;
+
+    // random games from the start position before the towns are placed,
+    // both sides are counted every ply
+    let start = Board::start_position();
+    let soldiers = [
+        start.pieces_with_color(Color::White),
+        start.pieces_with_color(Color::Black),
+    ];
+    let mut rng = Rng::new(7);
+    for _ in 0..20 {
+        let mut board = Board::from_bitboards(Color::White, soldiers, [EMPTY; 2]);
+        for _ in 0..60 {
+            for color in [Color::White, Color::Black] {
+                let mut to_move = board.shallow_clone();
+                if to_move.side_to_move() != color {
+                    to_move.flip_side();
+                }
+                assert_eq!(
+                    MoveGen::count(&board, color) as usize,
+                    MoveGen::generate(&to_move).len()
+                );
+            }
+            let moves = board.generate_moves();
+            if moves.is_empty() {
+                break;
+            }
+            let m = moves.get(rng.below(moves.len()));
+            if board.captures_town(m) {
+                break;
+            }
+            board.apply_move(m);
+        }
+    }
+}
+
+#[test]
+fn move_kind_test() {
+    let mut board = Board::new(Color::White);
+    board.set_castle(Color::White, Square::H1);
+    board.set_castle(Color::Black, Square::A8);
+    board.set(Color::White, Square::D4);
+    board.set(Color::White, Square::D3);
+    board.set(Color::White, Square::D2);
+    board.set(Color::Black, Square::E5);
+    board.set(Color::Black, Square::D7);
+
+    let hash = board.hash();
+    for m in board.generate_moves() {
+        let expected = match (m.src(), m.dst()) {
+            (Square::D4, Square::E5) => MoveKind::Capture,
+            (Square::D4, Square::D7) => MoveKind::Shot,
+            (Square::D2, Square::D5) | (Square::D4, Square::D1) => MoveKind::CannonMove,
+            (Square::D4, Square::B2) | (Square::D4, Square::F2) => MoveKind::Retreat,
+            _ => MoveKind::Step,
+        };
+        assert_eq!(m.kind(), expected, "{}", m);
+        assert!(MoveGen::is_pseudo_legal(&board, m));
+        assert_eq!(m.is_capture(), board.generate_captures().any(|c| c == m));
+
+        let enemies = board.enemy_pieces();
+        board.apply_move(m);
+        assert_eq!(
+            board.last_capture(),
+            m.kind() == MoveKind::Capture && (enemies & BitBoard::from_square(m.dst())) != EMPTY
+        );
+        board.undo_move();
+        assert_eq!(board.hash(), hash);
+    }
+}
+
+#[test]
+fn shot_hash_test() {
+    use crate::transposition::hash::{PIECES, SIDE};
+
+    let mut board = Board::new(Color::White);
+    board.set_castle(Color::White, Square::H1);
+    board.set_castle(Color::Black, Square::A8);
+    board.set(Color::White, Square::D4);
+    board.set(Color::White, Square::D3);
+    board.set(Color::White, Square::D2);
+    board.set(Color::Black, Square::D7);
+    board.set(Color::Black, Square::H8);
+
+    // a shot passes the turn like any other move, so the side to move is hashed as well
+    let hash = board.hash();
+    let shot = board.generate_moves().find(|m| m.is_shot()).unwrap();
+    board.apply_move(shot);
+    assert_eq!(
+        board.hash(),
+        hash ^ PIECES[Color::Black.to_index()][Square::D7.to_index()] ^ SIDE
+    );
+    board.undo_move();
+    assert_eq!(board.hash(), hash);
+}
+
+#[test]
+fn town_shot_test() {
+    // a diagonal cannon aiming at the black town
+    let mut board = Board::from_fen("7B/8/8/4w3/3w4/2w5/8/7W w").unwrap();
+    let shot = board
+        .generate_moves()
+        .find(|m| m.is_shot() && m.dst() == Square::H8)
+        .unwrap();
+    let (pieces, black, hash) = (
+        board.pieces(),
+        board.pieces_with_color(Color::Black),
+        board.hash(),
+    );
+    board.apply_move(shot);
+    // no soldier appears on the town
+    assert_eq!(board.pieces(), pieces);
+    assert_eq!(board.pieces_with_color(Color::Black), black);
+    assert_eq!(board.hash(), hash ^ crate::transposition::hash::SIDE);
+    board.undo_move();
+    assert_eq!((board.pieces(), board.hash()), (pieces, hash));
+}
+
+#[test]
+fn placement_test() {
+    let mut board = Board::new(Color::White);
+    board.set(Color::White, Square::C1);
+    let moves: Vec<BitMove> = board.generate_moves().collect();
+    // every free home rank square, the corners too
+    assert_eq!(moves.len(), 7);
+    assert!(moves
+        .iter()
+        .all(|m| m.is_placement() && m.dst().rank() == Rank::One));
+
+    board.apply_move(moves[0]);
+    assert_eq!(
+        board.castle_with_color(Color::White),
+        BitBoard::from_square(moves[0].dst())
+    );
+    board.undo_move();
+    assert_eq!(board.castle_with_color(Color::White), EMPTY);
+}
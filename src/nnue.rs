@@ -0,0 +1,368 @@
+use crate::board::Board;
+use crate::color::{Color, ALL_COLORS, NUM_COLORS};
+use crate::square::{Square, NUM_SQUARES};
+use std::fmt;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Soldiers and towns of both colors on every square.
+pub const NUM_FEATURES: usize = 4 * NUM_SQUARES;
+/// Size of the feature transformer for one perspective
+pub const HIDDEN: usize = 64;
+/// Size of the dense layer after the feature transformer
+pub const L1: usize = 16;
+/// Scale of the feature transformer weights and of all activations
+const QA: i32 = 127;
+/// Scale of the dense layer weights
+const QB: i32 = 64;
+/// Maps the network output to eval units
+const OUTPUT_SCALE: i32 = 16;
+/// Keeps network scores well away from the search's win scores
+const MAX_SCORE: i32 = 4000;
+const MAGIC: &[u8; 8] = b"CNNUE001";
+
+#[derive(Debug)]
+pub enum NnueError {
+    Io(std::io::Error),
+    InvalidFile(String),
+}
+
+impl fmt::Display for NnueError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NnueError::Io(e) => write!(f, "{}", e),
+            NnueError::InvalidFile(s) => write!(f, "invalid network file: {}", s),
+        }
+    }
+}
+
+impl std::error::Error for NnueError {}
+
+impl From<std::io::Error> for NnueError {
+    fn from(e: std::io::Error) -> Self {
+        NnueError::Io(e)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Feature {
+    Soldier,
+    Town,
+}
+
+/// Index of a piece seen from `perspective`, black sees the board mirrored
+/// so both colors share the same weights.
+#[inline(always)]
+fn feature_index(perspective: Color, feature: Feature, color: Color, square: Square) -> usize {
+    let relative = (color != perspective) as usize;
+    let sq = match perspective {
+        Color::White => square.to_index(),
+        Color::Black => square.to_index() ^ 56,
+    };
+    ((feature as usize * NUM_COLORS + relative) * NUM_SQUARES) + sq
+}
+
+/// Quantised weights of a feature transformer followed by two dense layers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Network {
+    ft_weights: Vec<i16>,
+    ft_bias: Vec<i16>,
+    l1_weights: Vec<i16>,
+    l1_bias: Vec<i32>,
+    out_weights: Vec<i16>,
+    out_bias: i32,
+}
+
+impl Network {
+    /// A network that evaluates every position as 0.
+    pub fn zeroed() -> Self {
+        Self {
+            ft_weights: vec![0; NUM_FEATURES * HIDDEN],
+            ft_bias: vec![0; HIDDEN],
+            l1_weights: vec![0; L1 * 2 * HIDDEN],
+            l1_bias: vec![0; L1],
+            out_weights: vec![0; L1],
+            out_bias: 0,
+        }
+    }
+
+    /// Reads the little endian format written by `to_bytes`: the magic, the layer sizes
+    /// and then every layer's weights followed by its biases.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, NnueError> {
+        let mut reader = Reader { bytes, pos: 0 };
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(NnueError::InvalidFile("wrong magic".to_string()));
+        }
+        let (hidden, l1) = (reader.u32()? as usize, reader.u32()? as usize);
+        if hidden != HIDDEN || l1 != L1 {
+            return Err(NnueError::InvalidFile(format!(
+                "expected layer sizes {} and {}, got {} and {}",
+                HIDDEN, L1, hidden, l1
+            )));
+        }
+        let network = Self {
+            ft_weights: reader.i16s(NUM_FEATURES * HIDDEN)?,
+            ft_bias: reader.i16s(HIDDEN)?,
+            l1_weights: reader.i16s(L1 * 2 * HIDDEN)?,
+            l1_bias: reader.i32s(L1)?,
+            out_weights: reader.i16s(L1)?,
+            out_bias: reader.i32()?,
+        };
+        if reader.pos != bytes.len() {
+            return Err(NnueError::InvalidFile("trailing bytes".to_string()));
+        }
+        Ok(network)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend((HIDDEN as u32).to_le_bytes());
+        bytes.extend((L1 as u32).to_le_bytes());
+        for w in self
+            .ft_weights
+            .iter()
+            .chain(&self.ft_bias)
+            .chain(&self.l1_weights)
+        {
+            bytes.extend(w.to_le_bytes());
+        }
+        for b in self.l1_bias.iter() {
+            bytes.extend(b.to_le_bytes());
+        }
+        for w in self.out_weights.iter() {
+            bytes.extend(w.to_le_bytes());
+        }
+        bytes.extend(self.out_bias.to_le_bytes());
+        bytes
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, NnueError> {
+        Self::from_bytes(&std::fs::read(path)?)
+    }
+
+    pub fn write_file<P: AsRef<Path>>(&self, path: P) -> Result<(), NnueError> {
+        std::fs::File::create(path)?.write_all(&self.to_bytes())?;
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn feature_weights(&self, index: usize) -> &[i16] {
+        &self.ft_weights[index * HIDDEN..(index + 1) * HIDDEN]
+    }
+
+    /// Runs the dense layers on the accumulator, the score is from the side to move.
+    fn forward(&self, accumulator: &Accumulator, side_to_move: Color) -> i16 {
+        let mut input = [0i32; 2 * HIDDEN];
+        let perspectives = [side_to_move, !side_to_move];
+        for (i, perspective) in perspectives.iter().enumerate() {
+            for (j, v) in accumulator.values[perspective.to_index()]
+                .iter()
+                .enumerate()
+            {
+                input[i * HIDDEN + j] = (*v as i32).clamp(0, QA);
+            }
+        }
+
+        let mut output = self.out_bias;
+        for (neuron, out_weight) in self.out_weights.iter().enumerate() {
+            let weights = &self.l1_weights[neuron * 2 * HIDDEN..(neuron + 1) * 2 * HIDDEN];
+            let sum: i32 = input
+                .iter()
+                .zip(weights)
+                .map(|(a, w)| a * *w as i32)
+                .sum::<i32>()
+                + self.l1_bias[neuron];
+            let activation = (sum / QB).clamp(0, QA);
+            output += activation * *out_weight as i32;
+        }
+        (output * OUTPUT_SCALE / (QA * QB)).clamp(-MAX_SCORE, MAX_SCORE) as i16
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], NnueError> {
+        let slice = self
+            .bytes
+            .get(self.pos..self.pos + n)
+            .ok_or_else(|| NnueError::InvalidFile("unexpected end of file".to_string()))?;
+        self.pos += n;
+        Ok(slice)
+    }
+
+    fn u32(&mut self) -> Result<u32, NnueError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn i32(&mut self) -> Result<i32, NnueError> {
+        Ok(i32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn i16s(&mut self, n: usize) -> Result<Vec<i16>, NnueError> {
+        Ok(self
+            .take(2 * n)?
+            .chunks_exact(2)
+            .map(|c| i16::from_le_bytes([c[0], c[1]]))
+            .collect())
+    }
+
+    fn i32s(&mut self, n: usize) -> Result<Vec<i32>, NnueError> {
+        (0..n).map(|_| self.i32()).collect()
+    }
+}
+
+/// The feature transformer output for both perspectives, kept in the board state
+/// and updated with every move.
+#[derive(Clone)]
+pub struct Accumulator {
+    values: [[i16; HIDDEN]; NUM_COLORS],
+    /// Updates are skipped without a network
+    network: Option<Arc<Network>>,
+}
+
+impl Default for Accumulator {
+    fn default() -> Self {
+        Self {
+            values: [[0; HIDDEN]; NUM_COLORS],
+            network: None,
+        }
+    }
+}
+
+impl Accumulator {
+    /// An accumulator for the empty board.
+    pub fn new(network: Arc<Network>) -> Self {
+        let mut values = [[0; HIDDEN]; NUM_COLORS];
+        for values in values.iter_mut() {
+            values.copy_from_slice(&network.ft_bias);
+        }
+        Self {
+            values,
+            network: Some(network),
+        }
+    }
+
+    pub fn from_board(board: &Board, network: Arc<Network>) -> Self {
+        let mut accumulator = Self::new(network);
+        for color in ALL_COLORS {
+            for sq in board.pieces_with_color(color) {
+                accumulator.add(Feature::Soldier, color, sq);
+            }
+            for sq in board.castle_with_color(color) {
+                accumulator.add(Feature::Town, color, sq);
+            }
+        }
+        accumulator
+    }
+
+    #[inline]
+    pub fn add(&mut self, feature: Feature, color: Color, square: Square) {
+        self.update(feature, color, square, |v, w| v.wrapping_add(w));
+    }
+
+    #[inline]
+    pub fn remove(&mut self, feature: Feature, color: Color, square: Square) {
+        self.update(feature, color, square, |v, w| v.wrapping_sub(w));
+    }
+
+    #[inline]
+    fn update<F: Fn(i16, i16) -> i16>(
+        &mut self,
+        feature: Feature,
+        color: Color,
+        square: Square,
+        op: F,
+    ) {
+        let Some(network) = &self.network else {
+            return;
+        };
+        for perspective in ALL_COLORS {
+            let weights =
+                network.feature_weights(feature_index(perspective, feature, color, square));
+            for (v, w) in self.values[perspective.to_index()].iter_mut().zip(weights) {
+                *v = op(*v, *w);
+            }
+        }
+    }
+}
+
+/// Evaluates the board with `network` from the side to move. Boards without the network
+/// in their accumulator are refreshed first.
+pub fn evaluate(network: &Arc<Network>, board: &Board) -> i16 {
+    let accumulator = board.accumulator();
+    if accumulator
+        .network
+        .as_ref()
+        .is_some_and(|n| Arc::ptr_eq(n, network))
+    {
+        network.forward(accumulator, board.side_to_move())
+    } else {
+        let accumulator = Accumulator::from_board(board, Arc::clone(network));
+        network.forward(&accumulator, board.side_to_move())
+    }
+}
+
+#[test]
+fn nnue_test() {
+    use crate::notation::parse_move;
+
+    // small pseudo random weights so every feature matters
+    let mut seed = 0x2545_f491_4f6c_dd1du64;
+    let mut next = |range: i64| {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        ((seed % (2 * range as u64 + 1)) as i64 - range) as i16
+    };
+    let mut net = Network::zeroed();
+    net.ft_weights.iter_mut().for_each(|w| *w = next(8));
+    net.ft_bias.iter_mut().for_each(|b| *b = next(16) + 16);
+    net.l1_weights.iter_mut().for_each(|w| *w = next(32));
+    net.out_weights.iter_mut().for_each(|w| *w = next(64));
+    net.out_bias = 1000;
+
+    let bytes = net.to_bytes();
+    assert_eq!(Network::from_bytes(&bytes).unwrap(), net);
+    assert!(Network::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+
+    let net = Arc::new(net);
+    let refreshed = |board: &Board| {
+        let accumulator = Accumulator::from_board(board, Arc::clone(&net));
+        net.forward(&accumulator, board.side_to_move())
+    };
+
+    // pieces set after the network are added to the accumulator
+    let mut board = Board::new(Color::White);
+    board.set_network(Arc::clone(&net));
+    board.set_castle(Color::White, Square::B1);
+    for sq in [Square::C3, Square::D6, Square::F2] {
+        board.set(Color::White, sq);
+    }
+    board.set(Color::Black, Square::E5);
+    assert_eq!(evaluate(&net, &board), refreshed(&board));
+
+    let mut board = Board::start_position();
+    let start = evaluate(&net, &board);
+    board.set_network(Arc::clone(&net));
+    assert_eq!(evaluate(&net, &board), start);
+    for m in ["c3d4", "d6d5", "d4c5", "b6c5"] {
+        let m = parse_move(&board, m).unwrap();
+        board.apply_move(m);
+        assert_eq!(evaluate(&net, &board), refreshed(&board));
+    }
+    for _ in 0..4 {
+        board.undo_move();
+    }
+    assert_eq!(evaluate(&net, &board), start);
+
+    // a searcher evaluates with its own network
+    let mut searcher = crate::search::Searcher::with_tt_size(1 << 12);
+    searcher.verbose = false;
+    searcher.network = Some(Arc::new(Network::zeroed()));
+    assert_eq!(searcher.search(&mut board, 2).score(), 0);
+}
@@ -0,0 +1,195 @@
+use crate::bitboard::BitBoard;
+use crate::board::Board;
+use crate::cannon_move::BitMove;
+use crate::color::Color;
+use crate::defs::{File, Rank};
+use crate::square::Square;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NotationError {
+    InvalidPosition(String),
+    InvalidSquare(String),
+    IllegalMove(String),
+}
+
+impl fmt::Display for NotationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NotationError::InvalidPosition(s) => write!(f, "invalid position {}", s),
+            NotationError::InvalidSquare(s) => write!(f, "invalid square {}", s),
+            NotationError::IllegalMove(s) => write!(f, "illegal move {}", s),
+        }
+    }
+}
+
+impl std::error::Error for NotationError {}
+
+pub fn parse_square(s: &str) -> Result<Square, NotationError> {
+    let bytes = s.as_bytes();
+    if bytes.len() != 2 || !(b'a'..=b'h').contains(&bytes[0]) || !(b'1'..=b'8').contains(&bytes[1])
+    {
+        return Err(NotationError::InvalidSquare(s.to_string()));
+    }
+    Ok(Square::make_square(
+        Rank::from_index(bytes[1] - b'1'),
+        File::from_index(bytes[0] - b'a'),
+    ))
+}
+
+/// Writes a move as source and destination square, e.g. `c3d4`.
+/// Placing a town uses the same square twice.
+pub fn move_to_string(m: BitMove) -> String {
+    format!("{}{}", m.src(), m.dst())
+}
+
+/// Parses a move in the format of `move_to_string`, the kind of the move is taken
+/// from the legal move with the same squares.
+pub fn parse_move(board: &Board, s: &str) -> Result<BitMove, NotationError> {
+    if s.len() != 4 || !s.is_ascii() {
+        return Err(NotationError::IllegalMove(s.to_string()));
+    }
+    let src = parse_square(&s[0..2])?;
+    let dst = parse_square(&s[2..4])?;
+    board
+        .generate_moves()
+        .find(|m| m.src() == src && m.dst() == dst)
+        .ok_or_else(|| NotationError::IllegalMove(s.to_string()))
+}
+
+impl Board {
+    /// Writes the position from rank 8 to rank 1 separated by `/`. White soldiers are `w`,
+    /// black soldiers `b`, the towns `W` and `B` and digits count empty squares.
+    /// The side to move follows after a space.
+    pub fn to_fen(&self) -> String {
+        let mut fen = String::new();
+        for rank in (0..8u8).rev() {
+            let mut empty = 0;
+            for file in 0..8u8 {
+                let sq = Square::make_square(Rank::from_index(rank), File::from_index(file));
+                let sq_bb = BitBoard::from_square(sq);
+                let c = match self.color_on(sq) {
+                    Some(Color::White) => Some('w'),
+                    Some(Color::Black) => Some('b'),
+                    None if (self.castle_with_color(Color::White) & sq_bb).is_not_empty() => {
+                        Some('W')
+                    }
+                    None if (self.castle_with_color(Color::Black) & sq_bb).is_not_empty() => {
+                        Some('B')
+                    }
+                    None => None,
+                };
+                match c {
+                    Some(c) => {
+                        if empty > 0 {
+                            fen.push((b'0' + empty) as char);
+                            empty = 0;
+                        }
+                        fen.push(c);
+                    }
+                    None => empty += 1,
+                }
+            }
+            if empty > 0 {
+                fen.push((b'0' + empty) as char);
+            }
+            if rank > 0 {
+                fen.push('/');
+            }
+        }
+        fen.push(' ');
+        fen.push_str(&self.side_to_move().to_string());
+        fen
+    }
+
+    pub fn from_fen(fen: &str) -> Result<Board, NotationError> {
+        let err = || NotationError::InvalidPosition(fen.to_string());
+        let mut parts = fen.split_whitespace();
+        let placement = parts.next().ok_or_else(err)?;
+        let color = match parts.next() {
+            Some("w") => Color::White,
+            Some("b") => Color::Black,
+            _ => return Err(err()),
+        };
+        if parts.next().is_some() {
+            return Err(err());
+        }
+
+        let mut board = Board::new(color);
+        let ranks: Vec<&str> = placement.split('/').collect();
+        if ranks.len() != 8 {
+            return Err(err());
+        }
+        for (i, rank_str) in ranks.iter().enumerate() {
+            let rank = Rank::from_index(7 - i as u8);
+            let mut file = 0u8;
+            for c in rank_str.chars() {
+                if let Some(d) = c.to_digit(10) {
+                    file = file
+                        .checked_add(d as u8)
+                        .filter(|f| *f <= 8)
+                        .ok_or_else(err)?;
+                    continue;
+                }
+                if file >= 8 {
+                    return Err(err());
+                }
+                let sq = Square::make_square(rank, File::from_index(file));
+                match c {
+                    'w' => board.set(Color::White, sq),
+                    'b' => board.set(Color::Black, sq),
+                    // one town per color, on its home rank
+                    'W' if rank == Rank::One
+                        && board.castle_with_color(Color::White).is_empty() =>
+                    {
+                        board.set_castle(Color::White, sq)
+                    }
+                    'B' if rank == Rank::Eight
+                        && board.castle_with_color(Color::Black).is_empty() =>
+                    {
+                        board.set_castle(Color::Black, sq)
+                    }
+                    _ => return Err(err()),
+                }
+                file += 1;
+            }
+            if file != 8 {
+                return Err(err());
+            }
+        }
+        Ok(board)
+    }
+}
+
+#[test]
+fn notation_test() {
+    let board = Board::start_position();
+    let fen = board.to_fen();
+    assert_eq!(
+        fen,
+        "Bb1b1b1b/1b1b1b1b/1b1b1b1b/8/8/w1w1w1w1/w1w1w1w1/w1w1w1wW w"
+    );
+    let parsed = Board::from_fen(&fen).unwrap();
+    assert_eq!(parsed.to_fen(), fen);
+    assert_eq!(parsed.hash(), board.hash());
+
+    let mut played = Board::start_position();
+    played.apply_move(parse_move(&played, "c3d4").unwrap());
+    let parsed = Board::from_fen(&played.to_fen()).unwrap();
+    assert_eq!(parsed.side_to_move(), Color::Black);
+    assert_eq!(parsed.hash(), played.hash());
+
+    let m = parse_move(&board, "a1a4").unwrap();
+    assert_eq!(move_to_string(m), "a1a4");
+    assert!(parse_move(&board, "a1a5").is_err());
+    assert!(Board::from_fen("8/8 w").is_err());
+    assert!(Board::from_fen(&fen.replace(" w", " x")).is_err());
+    // ranks past eight files, also with long digit runs that would overflow the file
+    assert!(Board::from_fen("B7/8/8/8/8/8/44w/7W w").is_err());
+    assert!(Board::from_fen(&format!("B7/8/8/8/8/8/{}/7W w", "9".repeat(40))).is_err());
+    // towns off the home rank or more than one town per color
+    assert!(Board::from_fen("8/B7/8/8/8/8/8/7W w").is_err());
+    assert!(Board::from_fen("B7/8/8/8/8/8/8/W6W w").is_err());
+    assert!(Board::from_fen("B6B/8/8/8/8/8/8/7W w").is_err());
+    assert!(Board::from_fen("B7/8/8/8/8/8/8/8 w").is_ok());
+}
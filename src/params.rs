@@ -0,0 +1,154 @@
+use std::fmt;
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum ParamsError {
+    Io(std::io::Error),
+    UnknownParam(String),
+    InvalidValue { name: String, value: String },
+    InvalidLine(usize),
+}
+
+impl fmt::Display for ParamsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParamsError::Io(e) => write!(f, "{}", e),
+            ParamsError::UnknownParam(name) => write!(f, "unknown parameter {}", name),
+            ParamsError::InvalidValue { name, value } => {
+                write!(f, "invalid value {} for parameter {}", value, name)
+            }
+            ParamsError::InvalidLine(line) => write!(f, "expected `name = value` on line {}", line),
+        }
+    }
+}
+
+impl std::error::Error for ParamsError {}
+
+impl From<std::io::Error> for ParamsError {
+    fn from(e: std::io::Error) -> Self {
+        ParamsError::Io(e)
+    }
+}
+
+/// Splits a parameter file into `(name, value)` pairs, skipping empty lines and `#` comments.
+pub fn parse_lines(input: &str) -> Result<Vec<(&str, &str)>, ParamsError> {
+    let mut pairs = Vec::new();
+    for (i, line) in input.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (name, value) = line
+            .split_once('=')
+            .ok_or(ParamsError::InvalidLine(i + 1))?;
+        pairs.push((name.trim(), value.trim()));
+    }
+    Ok(pairs)
+}
+
+/// A set of named integer parameters that can be changed at runtime,
+/// either one at a time as engine options or from a `name = value` file.
+pub trait Params: Default + fmt::Display {
+    /// Names of all parameters in the order they are written.
+    fn names() -> &'static [&'static str];
+
+    fn get(&self, name: &str) -> Option<i32>;
+
+    fn set(&mut self, name: &str, value: &str) -> Result<(), ParamsError>;
+
+    /// Parses a parameter file, parameters that are not mentioned keep their default value.
+    fn parse(input: &str) -> Result<Self, ParamsError> {
+        let mut params = Self::default();
+        for (name, value) in parse_lines(input)? {
+            params.set(name, value)?;
+        }
+        Ok(params)
+    }
+
+    fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, ParamsError> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    fn write_file<P: AsRef<Path>>(&self, path: P) -> Result<(), ParamsError> {
+        Ok(std::fs::write(path, self.to_string())?)
+    }
+}
+
+/// Defines a parameter struct with a default for every field and implements `Params` for it.
+macro_rules! define_params {
+    ($(#[$meta:meta])* $name:ident { $($(#[$fmeta:meta])* $field:ident: $t:ty = $default:expr,)* }) => {
+        $(#[$meta])*
+        #[derive(Clone, Debug, PartialEq, Eq)]
+        pub struct $name {
+            $($(#[$fmeta])* pub $field: $t,)*
+        }
+
+        impl Default for $name {
+            fn default() -> Self {
+                Self {
+                    $($field: $default,)*
+                }
+            }
+        }
+
+        impl $crate::params::Params for $name {
+            fn names() -> &'static [&'static str] {
+                &[$(stringify!($field),)*]
+            }
+
+            fn get(&self, name: &str) -> Option<i32> {
+                match name {
+                    $(stringify!($field) => Some(self.$field as i32),)*
+                    _ => None,
+                }
+            }
+
+            fn set(&mut self, name: &str, value: &str) -> Result<(), $crate::params::ParamsError> {
+                match name {
+                    $(stringify!($field) => {
+                        self.$field = value.parse().map_err(|_| {
+                            $crate::params::ParamsError::InvalidValue {
+                                name: name.to_string(),
+                                value: value.to_string(),
+                            }
+                        })?;
+                    })*
+                    _ => return Err($crate::params::ParamsError::UnknownParam(name.to_string())),
+                }
+                Ok(())
+            }
+        }
+
+        impl std::fmt::Display for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                $(writeln!(f, "{} = {}", stringify!($field), self.$field)?;)*
+                Ok(())
+            }
+        }
+    };
+}
+
+#[test]
+fn params_test() {
+    use crate::eval::EvalParams;
+    use crate::search::SearchParams;
+
+    let mut params = EvalParams::parse("# tuned\npiece_value = 10\n\n castle_cutoff=4").unwrap();
+    assert_eq!(params.piece_value, 10);
+    assert_eq!(params.castle_cutoff, 4);
+    assert_eq!(params.area_weight, EvalParams::default().area_weight);
+    assert_eq!(EvalParams::parse(&params.to_string()).unwrap(), params);
+
+    params.set("surround_divisor", "3").unwrap();
+    assert_eq!(params.get("surround_divisor"), Some(3));
+    assert!(params.set("surround_divisor", "x").is_err());
+    assert!(params.set("nope", "1").is_err());
+    assert!(EvalParams::parse("piece_value").is_err());
+
+    let search = SearchParams::parse("quiescence_depth = 8").unwrap();
+    assert_eq!(search.quiescence_depth, 8);
+    assert_eq!(
+        SearchParams::names().len(),
+        search.to_string().lines().count()
+    );
+}
@@ -0,0 +1,321 @@
+use crate::board::Board;
+use crate::cannon_move::BitMove;
+use crate::eval::{eval_with, EvalParams};
+use crate::mcts::MctsParams;
+use crate::mcts::MctsSearcher;
+use crate::notation::parse_move;
+use crate::params::{Params, ParamsError};
+use crate::rng::Rng;
+use crate::search::{SearchLimits, Searcher};
+use crate::skill::{SkillLevel, SkillPlayer};
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+/// Table size of searchers created from a spec, several of them run at once in matches.
+const SPEC_TT_SIZE: usize = 1 << 20;
+
+#[derive(Debug)]
+pub enum PlayerError {
+    Io(io::Error),
+    Params(ParamsError),
+    UnknownPlayer(String),
+}
+
+impl fmt::Display for PlayerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PlayerError::Io(e) => write!(f, "{}", e),
+            PlayerError::Params(e) => write!(f, "{}", e),
+            PlayerError::UnknownPlayer(s) => write!(f, "unknown player {}", s),
+        }
+    }
+}
+
+impl std::error::Error for PlayerError {}
+
+impl From<io::Error> for PlayerError {
+    fn from(e: io::Error) -> Self {
+        PlayerError::Io(e)
+    }
+}
+
+impl From<ParamsError> for PlayerError {
+    fn from(e: ParamsError) -> Self {
+        PlayerError::Params(e)
+    }
+}
+
+/// Anything that picks a move, so engines, test opponents and external programs can be swapped.
+/// A null move means the player has no move or gives up.
+pub trait Player {
+    fn name(&self) -> String;
+
+    fn choose_move(&mut self, board: &Board, limits: &SearchLimits) -> BitMove;
+
+    /// Called before every game, players with state from earlier games should reset it.
+    fn new_game(&mut self) {}
+}
+
+/// Picks a uniformly random legal move.
+pub struct RandomPlayer {
+    rng: Rng,
+}
+
+impl RandomPlayer {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: Rng::new(seed),
+        }
+    }
+}
+
+impl Player for RandomPlayer {
+    fn name(&self) -> String {
+        "random".to_string()
+    }
+
+    fn choose_move(&mut self, board: &Board, _limits: &SearchLimits) -> BitMove {
+        let moves = board.generate_moves();
+        if moves.is_empty() {
+            return BitMove::null();
+        }
+        moves.get(self.rng.below(moves.len()))
+    }
+}
+
+/// Plays the move with the best eval after one ply and always takes the town.
+#[derive(Default)]
+pub struct GreedyPlayer {
+    pub eval_params: EvalParams,
+}
+
+impl GreedyPlayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Player for GreedyPlayer {
+    fn name(&self) -> String {
+        "greedy".to_string()
+    }
+
+    fn choose_move(&mut self, board: &Board, _limits: &SearchLimits) -> BitMove {
+        let mut board = board.shallow_clone();
+        let mut best = (BitMove::null(), i16::MIN);
+        for m in board.generate_moves() {
+            if board.captures_town(m) {
+                return m;
+            }
+            board.apply_move(m);
+            let score = -eval_with(&board, &self.eval_params);
+            board.undo_move();
+            if score > best.1 {
+                best = (m, score);
+            }
+        }
+        best.0
+    }
+}
+
+impl Player for Searcher {
+    fn name(&self) -> String {
+        "alphabeta".to_string()
+    }
+
+    fn choose_move(&mut self, board: &Board, limits: &SearchLimits) -> BitMove {
+        self.search_with_limits(&mut board.shallow_clone(), limits)
+            .bitmove()
+    }
+
+    fn new_game(&mut self) {
+        self.clear();
+    }
+}
+
+impl Player for MctsSearcher {
+    fn name(&self) -> String {
+        "mcts".to_string()
+    }
+
+    fn choose_move(&mut self, board: &Board, limits: &SearchLimits) -> BitMove {
+        self.search_with_limits(&mut board.shallow_clone(), limits)
+            .bitmove()
+    }
+
+    fn new_game(&mut self) {
+        self.clear();
+    }
+}
+
+/// Another program speaking the protocol of the `cli` binary on stdin and stdout.
+pub struct ExternalPlayer {
+    name: String,
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl ExternalPlayer {
+    pub fn new(command: &str, args: &[&str]) -> io::Result<Self> {
+        let mut child = Command::new(command)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdin = child.stdin.take().unwrap();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        Ok(Self {
+            name: command.to_string(),
+            child,
+            stdin,
+            stdout,
+        })
+    }
+
+    /// Sends the position and reads lines until the engine answers with `bestmove`.
+    fn request_move(&mut self, board: &Board, limits: &SearchLimits) -> io::Result<BitMove> {
+        writeln!(self.stdin, "position fen {}", board.to_fen())?;
+        match (limits.nodes, limits.time) {
+            (Some(nodes), _) => writeln!(self.stdin, "go nodes {}", nodes)?,
+            (None, Some(time)) => writeln!(self.stdin, "go movetime {}", time.as_millis())?,
+            (None, None) => writeln!(self.stdin, "go depth {}", limits.depth)?,
+        }
+        self.stdin.flush()?;
+
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if self.stdout.read_line(&mut line)? == 0 {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "engine quit"));
+            }
+            let mut words = line.split_whitespace();
+            if words.next() == Some("bestmove") {
+                return Ok(match words.next() {
+                    Some("none") | None => BitMove::null(),
+                    Some(m) => parse_move(board, m).unwrap_or_else(|_| BitMove::null()),
+                });
+            }
+        }
+    }
+}
+
+impl Player for ExternalPlayer {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    /// A broken or illegal answer counts as giving up.
+    fn choose_move(&mut self, board: &Board, limits: &SearchLimits) -> BitMove {
+        self.request_move(board, limits)
+            .unwrap_or_else(|_| BitMove::null())
+    }
+}
+
+impl Drop for ExternalPlayer {
+    fn drop(&mut self) {
+        let _ = writeln!(self.stdin, "quit");
+        let _ = self.stdin.flush();
+        let _ = self.child.wait();
+    }
+}
+
+/// Creates a player from a short description, used by the match tools:
+/// `random[:seed]`, `greedy`, `alphabeta[:params file]`, `mcts[:params file]`,
+/// `skill:<level>[:seed]` or `cmd:<program> [args]` for an external engine.
+pub fn from_spec(spec: &str) -> Result<Box<dyn Player>, PlayerError> {
+    let (kind, arg) = match spec.split_once(':') {
+        Some((kind, arg)) => (kind, Some(arg)),
+        None => (spec, None),
+    };
+    Ok(match (kind, arg) {
+        ("random", seed) => {
+            let seed = match seed {
+                Some(seed) => seed
+                    .parse()
+                    .map_err(|_| PlayerError::UnknownPlayer(spec.to_string()))?,
+                None => 0,
+            };
+            Box::new(RandomPlayer::new(seed))
+        }
+        ("greedy", None) => Box::new(GreedyPlayer::new()),
+        ("alphabeta", path) => {
+            let mut searcher = Searcher::with_tt_size(SPEC_TT_SIZE);
+            searcher.verbose = false;
+            if let Some(path) = path {
+                searcher.load_params(path)?;
+            }
+            Box::new(searcher)
+        }
+        ("mcts", path) => {
+            let mut searcher = MctsSearcher::new();
+            if let Some(path) = path {
+                searcher.params = MctsParams::from_file(path)?;
+            }
+            Box::new(searcher)
+        }
+        ("skill", Some(arg)) => {
+            let unknown = || PlayerError::UnknownPlayer(spec.to_string());
+            let (level, seed) = match arg.split_once(':') {
+                Some((level, seed)) => (level, seed.parse().map_err(|_| unknown())?),
+                None => (arg, 0),
+            };
+            let level: SkillLevel = level.parse().map_err(|_| unknown())?;
+            Box::new(SkillPlayer::new(level, seed))
+        }
+        ("cmd", Some(command)) => {
+            let mut parts = command.split_whitespace();
+            let program = parts
+                .next()
+                .ok_or_else(|| PlayerError::UnknownPlayer(spec.to_string()))?;
+            let args: Vec<&str> = parts.collect();
+            Box::new(ExternalPlayer::new(program, &args)?)
+        }
+        _ => return Err(PlayerError::UnknownPlayer(spec.to_string())),
+    })
+}
+
+#[test]
+fn player_test() {
+    let board = Board::from_fen("B7/w7/8/8/8/8/8/7W w").unwrap();
+    let limits = SearchLimits::depth(4);
+    let mut players: Vec<Box<dyn Player>> = vec![
+        Box::new(RandomPlayer::new(1)),
+        Box::new(GreedyPlayer::new()),
+        Box::new(Searcher::with_tt_size(1 << 10)),
+    ];
+    for player in players.iter_mut() {
+        player.new_game();
+        let m = player.choose_move(&board, &limits);
+        assert!(board.generate_moves().contains(m), "{}", player.name());
+    }
+    // all but the random player take the town
+    for player in players.iter_mut().skip(1) {
+        assert!(board.captures_town(player.choose_move(&board, &limits)));
+    }
+
+    assert_eq!(from_spec("random:3").unwrap().name(), "random");
+    assert_eq!(from_spec("skill:casual:2").unwrap().name(), "skill:casual");
+    assert!(from_spec("greedy:x").is_err());
+    assert!(from_spec("nope").is_err());
+
+    let empty = Board::from_fen("B7/8/8/8/8/8/8/7W w").unwrap();
+    assert_eq!(
+        RandomPlayer::new(1).choose_move(&empty, &limits),
+        BitMove::null()
+    );
+
+    // searchers start every game without the table of the last one
+    let start = Board::start_position();
+    let mut searcher = Searcher::with_tt_size(1 << 10);
+    searcher.choose_move(&start, &limits);
+    assert!(searcher.tt.get(start.hash()).is_some());
+    Player::new_game(&mut searcher);
+    assert!(searcher.tt.get(start.hash()).is_none());
+    let mut mcts = MctsSearcher::new();
+    mcts.choose_move(&board, &SearchLimits::depth(2));
+    assert!(!mcts.root_visits().is_empty());
+    Player::new_game(&mut mcts);
+    assert!(mcts.root_visits().is_empty());
+}
@@ -0,0 +1,339 @@
+use crate::board::Board;
+use crate::cannon_move::BitMove;
+use crate::color::Color;
+use std::collections::{HashMap, HashSet};
+
+const INFINITY: u32 = u32::MAX;
+const NO_PARENT: usize = usize::MAX;
+
+/// Outcome of a proof for the side to move.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Proof {
+    Win,
+    Loss,
+    /// Neither side can force the capture of a town without repeating a position
+    Draw,
+    /// The node budget ran out first
+    Unknown,
+}
+
+#[derive(Clone, Debug)]
+pub struct ProofResult {
+    pub proof: Proof,
+    /// Moves of the proof from the position, the winner plays the fastest win
+    /// and the loser the longest defence
+    pub line: Vec<BitMove>,
+    pub nodes: usize,
+}
+
+fn sum(numbers: impl Iterator<Item = u32>) -> u32 {
+    numbers.fold(0, |acc, n| acc.saturating_add(n))
+}
+
+#[derive(Clone)]
+struct Node {
+    mv: BitMove,
+    parent: usize,
+    first_child: usize,
+    num_children: usize,
+    expanded: bool,
+    pn: u32,
+    dn: u32,
+    /// Plies from the node until the town falls or the defender has no move, once proven
+    distance: u16,
+}
+
+impl Node {
+    fn new(mv: BitMove, parent: usize) -> Self {
+        Self {
+            mv,
+            parent,
+            first_child: 0,
+            num_children: 0,
+            expanded: false,
+            pn: 1,
+            dn: 1,
+            distance: 0,
+        }
+    }
+
+    fn proven(&mut self, distance: u16) {
+        self.pn = 0;
+        self.dn = INFINITY;
+        self.distance = distance;
+    }
+
+    fn disproven(&mut self) {
+        self.pn = INFINITY;
+        self.dn = 0;
+    }
+}
+
+/// Proof-number search that proves whether a side can force the capture of the enemy town.
+/// Proven positions are shared between the branches of a proof by their hash, repeating
+/// a position on the current line counts as failing to win.
+pub struct ProofSearch {
+    /// Nodes the tree may grow to for each of the two proofs `solve` runs
+    pub max_nodes: usize,
+    /// Distance of positions proven as a win for the given attacker
+    proven: HashMap<(u64, Color), u16>,
+}
+
+impl ProofSearch {
+    pub fn new(max_nodes: usize) -> Self {
+        Self {
+            max_nodes,
+            proven: HashMap::new(),
+        }
+    }
+
+    /// Tries to prove a win for the side to move, then a win for the other side.
+    pub fn solve(&mut self, board: &Board) -> ProofResult {
+        let side = board.side_to_move();
+        let (win, line, nodes) = self.prove(board, side);
+        match win {
+            Some(true) => {
+                return ProofResult {
+                    proof: Proof::Win,
+                    line,
+                    nodes,
+                }
+            }
+            None => {
+                return ProofResult {
+                    proof: Proof::Unknown,
+                    line: Vec::new(),
+                    nodes,
+                }
+            }
+            Some(false) => {}
+        }
+        let (loss, line, more) = self.prove(board, !side);
+        let proof = match loss {
+            Some(true) => Proof::Loss,
+            Some(false) => Proof::Draw,
+            None => Proof::Unknown,
+        };
+        ProofResult {
+            proof,
+            line,
+            nodes: nodes + more,
+        }
+    }
+
+    /// Whether `attacker` can force a win, None if the budget ran out,
+    /// with the proof's line and the size of the tree.
+    fn prove(&mut self, root: &Board, attacker: Color) -> (Option<bool>, Vec<BitMove>, usize) {
+        let mut board = root.shallow_clone();
+        let mut tree = vec![Node::new(BitMove::null(), NO_PARENT)];
+        let root_terminal = self.evaluate(&board, attacker, &HashSet::new());
+        if let Some(result) = root_terminal {
+            match result {
+                Some(distance) => tree[0].proven(distance),
+                None => tree[0].disproven(),
+            }
+        }
+
+        while tree[0].pn != 0 && tree[0].dn != 0 {
+            if tree.len() >= self.max_nodes {
+                return (None, Vec::new(), tree.len());
+            }
+            // walk down to the most proving node
+            let mut node = 0;
+            let mut path = HashSet::from([board.hash()]);
+            while tree[node].expanded {
+                let children =
+                    tree[node].first_child..tree[node].first_child + tree[node].num_children;
+                node = if board.side_to_move() == attacker {
+                    children.min_by_key(|&c| tree[c].pn).unwrap()
+                } else {
+                    children.min_by_key(|&c| tree[c].dn).unwrap()
+                };
+                board.apply_move(tree[node].mv);
+                path.insert(board.hash());
+            }
+
+            self.expand(&mut tree, node, &mut board, attacker, &path);
+            // update the ancestors and go back to the root
+            loop {
+                self.update(&mut tree, node, board.side_to_move() == attacker);
+                if tree[node].pn == 0 {
+                    self.proven
+                        .insert((board.hash(), attacker), tree[node].distance);
+                }
+                if tree[node].parent == NO_PARENT {
+                    break;
+                }
+                node = tree[node].parent;
+                board.undo_move();
+            }
+        }
+
+        let won = tree[0].pn == 0;
+        let line = if won {
+            Self::line(&tree, &mut board, attacker)
+        } else {
+            Vec::new()
+        };
+        (Some(won), line, tree.len())
+    }
+
+    /// The distance of a win for `attacker`, None for a failed proof,
+    /// or no result if the position has to be searched.
+    fn evaluate(&self, board: &Board, attacker: Color, path: &HashSet<u64>) -> Option<Option<u16>> {
+        let moves = board.generate_moves();
+        let attacking = board.side_to_move() == attacker;
+        if moves.is_empty() {
+            return Some(if attacking { None } else { Some(0) });
+        }
+        if let Some(&distance) = self.proven.get(&(board.hash(), attacker)) {
+            return Some(Some(distance));
+        }
+        if path.contains(&board.hash()) {
+            return Some(None);
+        }
+        None
+    }
+
+    fn expand(
+        &self,
+        tree: &mut Vec<Node>,
+        node: usize,
+        board: &mut Board,
+        attacker: Color,
+        path: &HashSet<u64>,
+    ) {
+        let moves = board.generate_moves();
+        let attacking = board.side_to_move() == attacker;
+        tree[node].first_child = tree.len();
+        tree[node].num_children = moves.len();
+        tree[node].expanded = true;
+        for m in moves {
+            let mut child = Node::new(m, node);
+            let takes_town = board.captures_town(m);
+            if takes_town {
+                if attacking {
+                    child.proven(0);
+                } else {
+                    child.disproven();
+                }
+            } else {
+                board.apply_move(m);
+                match self.evaluate(board, attacker, path) {
+                    Some(Some(distance)) => child.proven(distance),
+                    Some(None) => child.disproven(),
+                    None => {}
+                }
+                board.undo_move();
+            }
+            tree.push(child);
+        }
+    }
+
+    /// Recomputes the numbers of an expanded node from its children, `attacking` if the
+    /// attacker is to move at the node. Distances count the plies from the node.
+    fn update(&self, tree: &mut [Node], node: usize, attacking: bool) {
+        if !tree[node].expanded {
+            return;
+        }
+        let children =
+            &tree[tree[node].first_child..tree[node].first_child + tree[node].num_children];
+        let (pn, dn, distance) = if attacking {
+            let pn = children.iter().map(|c| c.pn).min().unwrap_or(INFINITY);
+            let dn = sum(children.iter().map(|c| c.dn));
+            let distance = children
+                .iter()
+                .filter(|c| c.pn == 0)
+                .map(|c| c.distance)
+                .min()
+                .unwrap_or(0);
+            (pn, dn, distance)
+        } else {
+            // a defender without moves has lost, so every node here has children
+            let pn = sum(children.iter().map(|c| c.pn));
+            let dn = children.iter().map(|c| c.dn).min().unwrap_or(0);
+            let distance = children.iter().map(|c| c.distance).max().unwrap_or(0);
+            (pn, dn, distance)
+        };
+        let n = &mut tree[node];
+        n.pn = pn;
+        n.dn = dn;
+        n.distance = if pn == 0 { distance + 1 } else { 0 };
+    }
+
+    /// Follows the proof from the root, which is left at `board`.
+    fn line(tree: &[Node], board: &mut Board, attacker: Color) -> Vec<BitMove> {
+        let mut line = Vec::new();
+        let mut node = 0;
+        while tree[node].expanded {
+            let children = tree[node].first_child..tree[node].first_child + tree[node].num_children;
+            let next = if board.side_to_move() == attacker {
+                children
+                    .filter(|&c| tree[c].pn == 0)
+                    .min_by_key(|&c| tree[c].distance)
+            } else {
+                children.max_by_key(|&c| tree[c].distance)
+            };
+            let Some(next) = next else {
+                break;
+            };
+            node = next;
+            line.push(tree[node].mv);
+            board.apply_move(tree[node].mv);
+        }
+        for _ in 0..line.len() {
+            board.undo_move();
+        }
+        line
+    }
+}
+
+#[test]
+fn proof_test() {
+    use crate::notation::move_to_string;
+    use crate::tablebase::{Tablebase, TbResult};
+
+    let board = Board::from_fen("B7/w7/8/8/8/8/8/7W w").unwrap();
+    let result = ProofSearch::new(1000).solve(&board);
+    assert_eq!(result.proof, Proof::Win);
+    assert_eq!(result.line.len(), 1);
+    assert_eq!(move_to_string(result.line[0]), "a7a8");
+
+    let board = Board::from_fen("B7/8/8/8/8/8/8/7W w").unwrap();
+    assert_eq!(ProofSearch::new(1000).solve(&board).proof, Proof::Loss);
+
+    // the solver agrees with the tablebase whenever it finishes
+    let tablebase = Tablebase::generate(
+        1,
+        &[(crate::square::Square::H1, crate::square::Square::A8)],
+        |_| {},
+    );
+    let mut solved = 0;
+    for fen in [
+        "B7/8/8/w7/8/8/8/7W w",
+        "B7/8/8/w7/8/8/8/7W b",
+        "B7/8/1b6/8/8/8/6w1/7W w",
+        "B7/8/8/8/3b4/3w4/8/7W b",
+        "B7/8/8/2b5/8/8/8/w6W w",
+        "B7/8/8/8/8/8/6b1/w6W w",
+    ] {
+        let board = Board::from_fen(fen).unwrap();
+        let result = ProofSearch::new(20000).solve(&board);
+        let expected = match tablebase.probe(&board).unwrap() {
+            TbResult::Win(_) => Proof::Win,
+            TbResult::Loss(_) => Proof::Loss,
+            TbResult::Draw => Proof::Draw,
+        };
+        if result.proof != Proof::Unknown {
+            assert_eq!(result.proof, expected, "{}", fen);
+            solved += 1;
+        }
+        // proven positions end the line early, but every move of it is legal
+        let mut board = board.shallow_clone();
+        for m in result.line {
+            assert!(board.generate_moves().contains(m), "{}", fen);
+            board.apply_move(m);
+        }
+    }
+    assert!(solved >= 4);
+}
@@ -0,0 +1,268 @@
+use crate::board::Board;
+use crate::book::Book;
+use crate::cannon_move::BitMove;
+#[cfg(not(feature = "nnue"))]
+use crate::eval::eval_with;
+#[cfg(feature = "nnue")]
+use crate::eval::eval_with_network;
+use crate::eval::{eval_trace_with, EvalParams};
+#[cfg(feature = "nnue")]
+use crate::nnue::Network;
+use crate::notation::{move_to_string, parse_move, NotationError};
+use crate::params::ParamsError;
+use crate::proof::ProofSearch;
+use crate::search::{set_search_option, SearchLimits, SearchOptions, SearchParams, Searcher};
+use crate::tablebase::Tablebase;
+use std::io::{self, BufRead, Write};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Answers the line based text protocol of the `cli` binary:
+///
+/// - `position startpos [moves <move>...]` or `position fen <position> [moves <move>...]`
+/// - `go depth <n>`, `go nodes <n>` or `go movetime <ms>` answers `bestmove <move> score <score>` or `bestmove none`
+/// - `solve <nodes>` answers `solve <win|loss|draw|unknown> line <move>...` from the side to move
+/// - `eval` answers `eval <score>` from the side to move
+/// - `trace` prints every evaluation term for both colors
+/// - `setoption <name> <value>` sets a search or eval parameter, or turns a search
+///   technique on or off with `true` or `false`
+/// - `fen` prints the current position
+/// - `loadbook <path>` loads an opening book the search plays from
+/// - `loadtb <dir>` loads the endgame tables the search probes
+/// - `loadnet <path>` loads the evaluation network, only with the `nnue` feature
+/// - `quit`
+///
+/// With `stats` on, every search answer is followed by lines starting with `stats`
+/// that show the work of each iteration and the counters of the search.
+///
+/// Errors are answered with a line starting with `error`.
+pub struct Engine {
+    pub board: Board,
+    pub search_params: SearchParams,
+    pub search_options: SearchOptions,
+    pub eval_params: EvalParams,
+    pub tablebase: Option<Arc<Tablebase>>,
+    pub book: Option<Arc<Book>>,
+    #[cfg(feature = "nnue")]
+    pub network: Option<Arc<Network>>,
+    /// Prints the search statistics after every search
+    pub stats: bool,
+    searcher: Option<Searcher>,
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Engine {
+    pub fn new() -> Self {
+        Self {
+            board: Board::start_position(),
+            search_params: SearchParams::default(),
+            search_options: SearchOptions::default(),
+            eval_params: EvalParams::default(),
+            tablebase: None,
+            book: None,
+            #[cfg(feature = "nnue")]
+            network: None,
+            stats: false,
+            searcher: None,
+        }
+    }
+
+    /// The searcher is only created on the first search since it allocates the TT.
+    fn searcher(&mut self) -> &mut Searcher {
+        let searcher = self.searcher.get_or_insert_with(|| {
+            let mut searcher = Searcher::new();
+            // stdout only carries protocol answers
+            searcher.verbose = false;
+            searcher
+        });
+        searcher.params = self.search_params.clone();
+        searcher.options = self.search_options.clone();
+        searcher.eval_params = self.eval_params.clone();
+        searcher.tablebase = self.tablebase.clone();
+        searcher.book = self.book.clone();
+        #[cfg(feature = "nnue")]
+        {
+            searcher.network = self.network.clone();
+        }
+        searcher
+    }
+
+    pub fn set_option(&mut self, name: &str, value: &str) -> Result<(), ParamsError> {
+        set_search_option(
+            &mut self.search_params,
+            &mut self.search_options,
+            &mut self.eval_params,
+            name,
+            value,
+        )
+    }
+
+    fn set_position(&mut self, args: &[&str]) -> Result<(), NotationError> {
+        let moves_idx = args
+            .iter()
+            .position(|a| *a == "moves")
+            .unwrap_or(args.len());
+        let mut board = match args.first() {
+            Some(&"startpos") => Board::start_position(),
+            Some(&"fen") => Board::from_fen(&args[1..moves_idx].join(" "))?,
+            _ => return Err(NotationError::InvalidPosition(args.join(" "))),
+        };
+        for m in args.iter().skip(moves_idx + 1) {
+            let m = parse_move(&board, m)?;
+            board.apply_move(m);
+        }
+        self.board = board;
+        Ok(())
+    }
+
+    /// Handles one command and returns false if the engine should quit.
+    pub fn handle<W: Write>(&mut self, line: &str, out: &mut W) -> io::Result<bool> {
+        let args: Vec<&str> = line.split_whitespace().collect();
+        match args.first() {
+            None => {}
+            Some(&"quit") => return Ok(false),
+            Some(&"position") => {
+                if let Err(e) = self.set_position(&args[1..]) {
+                    writeln!(out, "error {}", e)?;
+                }
+            }
+            Some(&"go") => {
+                let limits = match args.get(1..3) {
+                    Some(["depth", depth]) => depth.parse().ok().map(SearchLimits::depth),
+                    Some(["nodes", nodes]) => nodes.parse().ok().map(SearchLimits::nodes),
+                    Some(["movetime", ms]) => ms
+                        .parse()
+                        .ok()
+                        .map(|ms| SearchLimits::time(Duration::from_millis(ms))),
+                    _ => None,
+                };
+                match limits {
+                    Some(limits) => {
+                        let mut board = self.board.shallow_clone();
+                        let result = self.searcher().search_with_stats(&mut board, &limits);
+                        let m = result.best;
+                        if m.bitmove() == BitMove::null() {
+                            writeln!(out, "bestmove none")?;
+                        } else {
+                            let mv = move_to_string(m.bitmove());
+                            writeln!(out, "bestmove {} score {}", mv, m.score())?;
+                        }
+                        if self.stats {
+                            for line in result.stats.to_string().lines() {
+                                writeln!(out, "stats {}", line)?;
+                            }
+                        }
+                    }
+                    None => writeln!(
+                        out,
+                        "error expected go depth <n>, go nodes <n> or go movetime <ms>"
+                    )?,
+                }
+            }
+            Some(&"solve") => match args.get(1).and_then(|n| n.parse().ok()) {
+                Some(nodes) => {
+                    let result = ProofSearch::new(nodes).solve(&self.board);
+                    let line: Vec<String> = result.line.into_iter().map(move_to_string).collect();
+                    let proof = format!("{:?}", result.proof).to_lowercase();
+                    writeln!(out, "solve {} line {}", proof, line.join(" "))?;
+                }
+                None => writeln!(out, "error expected solve <nodes>")?,
+            },
+            Some(&"eval") => {
+                #[cfg(feature = "nnue")]
+                let score =
+                    eval_with_network(&self.board, &self.eval_params, self.network.as_ref());
+                #[cfg(not(feature = "nnue"))]
+                let score = eval_with(&self.board, &self.eval_params);
+                writeln!(out, "eval {}", score)?;
+            }
+            Some(&"trace") => {
+                writeln!(out, "{}", eval_trace_with(&self.board, &self.eval_params))?;
+            }
+            Some(&"setoption") => match args.get(1..3) {
+                Some([name, value]) => {
+                    if let Err(e) = self.set_option(name, value) {
+                        writeln!(out, "error {}", e)?;
+                    }
+                }
+                _ => writeln!(out, "error expected setoption <name> <value>")?,
+            },
+            Some(&"fen") => writeln!(out, "{}", self.board.to_fen())?,
+            Some(&"loadbook") => match args.get(1) {
+                Some(path) => match Book::from_file(path) {
+                    Ok(book) => self.book = Some(Arc::new(book)),
+                    Err(e) => writeln!(out, "error {}", e)?,
+                },
+                None => writeln!(out, "error expected loadbook <path>")?,
+            },
+            Some(&"loadtb") => match args.get(1) {
+                Some(dir) => match Tablebase::load_dir(dir) {
+                    Ok(tablebase) => self.tablebase = Some(Arc::new(tablebase)),
+                    Err(e) => writeln!(out, "error {}", e)?,
+                },
+                None => writeln!(out, "error expected loadtb <dir>")?,
+            },
+            #[cfg(feature = "nnue")]
+            Some(&"loadnet") => match args.get(1) {
+                Some(path) => match Network::from_file(path) {
+                    Ok(network) => self.network = Some(Arc::new(network)),
+                    Err(e) => writeln!(out, "error {}", e)?,
+                },
+                None => writeln!(out, "error expected loadnet <path>")?,
+            },
+            Some(command) => writeln!(out, "error unknown command {}", command)?,
+        }
+        out.flush()?;
+        Ok(true)
+    }
+}
+
+impl Engine {
+    /// Answers commands until the input ends or `quit`.
+    pub fn run<R: BufRead, W: Write>(&mut self, input: R, mut output: W) -> io::Result<()> {
+        for line in input.lines() {
+            if !self.handle(&line?, &mut output)? {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn run<R: BufRead, W: Write>(input: R, output: W) -> io::Result<()> {
+    Engine::new().run(input, output)
+}
+
+#[test]
+fn protocol_test() {
+    let input = "position startpos moves c3d4 d6d5\nfen\neval\ntrace\nsetoption piece_value 9\nsetoption futility_pruning false\nsetoption killer_moves 2\nsetoption nope 1\nfoo\nposition fen B7/w7/8/8/8/8/8/7W w\nsolve 100\nquit\nfen\n";
+    let mut output = Vec::new();
+    run(input.as_bytes(), &mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(
+        lines[0],
+        "Bb1b1b1b/1b1b1b1b/1b3b1b/3b4/3w4/w3w1w1/w1w1w1w1/w1w1w1wW w"
+    );
+    assert!(lines[1].starts_with("eval "));
+    assert!(lines[2].starts_with("term"));
+    assert!(output.contains("error unknown parameter nope"));
+    assert!(output.contains("error invalid value 2 for parameter killer_moves"));
+    assert!(output.contains("error unknown command foo"));
+    assert!(output.contains("solve win line a7a8"));
+    // nothing is answered after quit
+    assert_eq!(output.matches("Bb1b1b1b").count(), 1);
+
+    let mut engine = Engine::new();
+    engine.stats = true;
+    let mut output = Vec::new();
+    engine.run("go depth 4\n".as_bytes(), &mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.contains("stats depth 4 nodes"));
+    assert!(output.contains("stats nodes"));
+}
@@ -0,0 +1,208 @@
+use crate::board::Board;
+use crate::cannon_move::BitMove;
+use crate::color::Color;
+use crate::notation::{move_to_string, parse_move, NotationError};
+use std::fmt;
+use std::io::Write;
+use std::path::Path;
+use std::str::FromStr;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum GameResult {
+    WhiteWin,
+    BlackWin,
+    Draw,
+}
+
+impl GameResult {
+    pub fn win_for(color: Color) -> Self {
+        match color {
+            Color::White => GameResult::WhiteWin,
+            Color::Black => GameResult::BlackWin,
+        }
+    }
+
+    /// 1 for a win of `color`, 0.5 for a draw and 0 for a loss.
+    pub fn score_for(self, color: Color) -> f64 {
+        match (self, color) {
+            (GameResult::Draw, _) => 0.5,
+            (GameResult::WhiteWin, Color::White) | (GameResult::BlackWin, Color::Black) => 1.0,
+            _ => 0.0,
+        }
+    }
+}
+
+impl fmt::Display for GameResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GameResult::WhiteWin => write!(f, "1-0"),
+            GameResult::BlackWin => write!(f, "0-1"),
+            GameResult::Draw => write!(f, "1/2-1/2"),
+        }
+    }
+}
+
+impl FromStr for GameResult {
+    type Err = RecordError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "1-0" => Ok(GameResult::WhiteWin),
+            "0-1" => Ok(GameResult::BlackWin),
+            "1/2-1/2" => Ok(GameResult::Draw),
+            _ => Err(RecordError::InvalidRecord(s.to_string())),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum RecordError {
+    Io(std::io::Error),
+    Notation(NotationError),
+    InvalidRecord(String),
+}
+
+impl fmt::Display for RecordError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RecordError::Io(e) => write!(f, "{}", e),
+            RecordError::Notation(e) => write!(f, "{}", e),
+            RecordError::InvalidRecord(s) => write!(f, "invalid game record {}", s),
+        }
+    }
+}
+
+impl std::error::Error for RecordError {}
+
+impl From<std::io::Error> for RecordError {
+    fn from(e: std::io::Error) -> Self {
+        RecordError::Io(e)
+    }
+}
+
+impl From<NotationError> for RecordError {
+    fn from(e: NotationError) -> Self {
+        RecordError::Notation(e)
+    }
+}
+
+/// A finished game, stored as one line of `start position;result;moves`,
+/// followed by `;white;black` if the players are known.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GameRecord {
+    pub start: String,
+    pub moves: Vec<BitMove>,
+    pub result: GameResult,
+    /// Names of the white and black player
+    pub players: Option<(String, String)>,
+}
+
+impl GameRecord {
+    pub fn new(start: &Board, moves: Vec<BitMove>, result: GameResult) -> Self {
+        Self {
+            start: start.to_fen(),
+            moves,
+            result,
+            players: None,
+        }
+    }
+
+    /// Names must not contain `;`.
+    pub fn with_players(mut self, white: &str, black: &str) -> Self {
+        self.players = Some((white.to_string(), black.to_string()));
+        self
+    }
+
+    pub fn to_line(&self) -> String {
+        let moves: Vec<String> = self.moves.iter().map(|m| move_to_string(*m)).collect();
+        let line = format!("{};{};{}", self.start, self.result, moves.join(" "));
+        match &self.players {
+            Some((white, black)) => format!("{};{};{}", line, white, black),
+            None => line,
+        }
+    }
+
+    /// Parses a line written by `to_line`, every move is checked to be legal.
+    pub fn parse_line(line: &str) -> Result<Self, RecordError> {
+        let mut parts = line.trim().split(';');
+        let (start, result, moves) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(start), Some(result), Some(moves)) => (start, result, moves),
+            _ => return Err(RecordError::InvalidRecord(line.to_string())),
+        };
+        let players = match (parts.next(), parts.next(), parts.next()) {
+            (None, _, _) => None,
+            (Some(white), Some(black), None) => Some((white.to_string(), black.to_string())),
+            _ => return Err(RecordError::InvalidRecord(line.to_string())),
+        };
+        let mut board = Board::from_fen(start)?;
+        let mut parsed = Vec::new();
+        for m in moves.split_whitespace() {
+            let m = parse_move(&board, m)?;
+            board.apply_move(m);
+            parsed.push(m);
+        }
+        Ok(Self {
+            start: start.to_string(),
+            moves: parsed,
+            result: result.parse()?,
+            players,
+        })
+    }
+
+    pub fn start_board(&self) -> Board {
+        Board::from_fen(&self.start).expect("game record with invalid start position")
+    }
+
+    /// Every position of the game before each move, paired with the move played in it.
+    pub fn positions(&self) -> Vec<(Board, BitMove)> {
+        let mut board = self.start_board();
+        let mut positions = Vec::with_capacity(self.moves.len());
+        for m in self.moves.iter() {
+            positions.push((board.shallow_clone(), *m));
+            board.apply_move(*m);
+        }
+        positions
+    }
+}
+
+pub fn read_records<P: AsRef<Path>>(path: P) -> Result<Vec<GameRecord>, RecordError> {
+    std::fs::read_to_string(path)?
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(GameRecord::parse_line)
+        .collect()
+}
+
+pub fn append_records<P: AsRef<Path>>(path: P, records: &[GameRecord]) -> Result<(), RecordError> {
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    for record in records {
+        writeln!(file, "{}", record.to_line())?;
+    }
+    Ok(())
+}
+
+#[test]
+fn record_test() {
+    let mut board = Board::start_position();
+    let start = board.shallow_clone();
+    let mut moves = Vec::new();
+    for m in ["c3d4", "d6d5", "e3e4"] {
+        let m = parse_move(&board, m).unwrap();
+        board.apply_move(m);
+        moves.push(m);
+    }
+    let record = GameRecord::new(&start, moves, GameResult::Draw);
+    let line = record.to_line();
+    assert!(line.ends_with(";1/2-1/2;c3d4 d6d5 e3e4"));
+    assert_eq!(GameRecord::parse_line(&line).unwrap(), record);
+    assert_eq!(record.positions().len(), 3);
+    assert!(GameRecord::parse_line(&line.replace("e3e4", "e3e5")).is_err());
+    assert_eq!(GameResult::BlackWin.score_for(Color::Black), 1.0);
+
+    let named = record.with_players("alphabeta", "greedy");
+    assert!(named.to_line().ends_with("e3e4;alphabeta;greedy"));
+    assert_eq!(GameRecord::parse_line(&named.to_line()).unwrap(), named);
+}
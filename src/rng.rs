@@ -0,0 +1,42 @@
+/// A small seeded xorshift generator for random openings and noise,
+/// the same seed always gives the same sequence.
+#[derive(Clone, Debug)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // splitmix the seed so close seeds give unrelated sequences, xorshift needs a non zero state
+        let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        Self((z ^ (z >> 31)) | 1)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(2685821657736338717u64)
+    }
+
+    /// A number in `0..n`, `n` has to be positive.
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    /// A number in `-range..=range`.
+    pub fn symmetric(&mut self, range: i16) -> i16 {
+        (self.below(2 * range.max(0) as usize + 1) as i32 - range.max(0) as i32) as i16
+    }
+}
+
+#[test]
+fn rng_test() {
+    let mut a = Rng::new(7);
+    let mut b = Rng::new(7);
+    let first: Vec<u64> = (0..8).map(|_| a.next_u64()).collect();
+    assert_eq!(first, (0..8).map(|_| b.next_u64()).collect::<Vec<_>>());
+    assert_ne!(Rng::new(8).next_u64(), first[0]);
+    assert!((0..100).all(|_| a.below(5) < 5));
+    assert!((0..100).all(|_| a.symmetric(3).abs() <= 3));
+}
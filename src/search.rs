@@ -1,66 +1,476 @@
 use crate::board::Board;
+use crate::book::Book;
 use crate::cannon_move::BitMove;
 use crate::cannon_move::MoveWithScore;
-use crate::eval::eval;
+#[cfg(not(feature = "nnue"))]
+use crate::eval::eval_with;
+#[cfg(feature = "nnue")]
+use crate::eval::eval_with_network;
+use crate::eval::EvalParams;
+use crate::move_picker::MovePicker;
+#[cfg(feature = "nnue")]
+use crate::nnue::Network;
+use crate::params::{parse_lines, Params, ParamsError};
+use crate::rng::Rng;
+use crate::see::see;
+use crate::tablebase::Tablebase;
 use crate::transposition::*;
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-// TODO find good values
-const QUIESCENCE_DEPTH: u8 = 14;
-const WINDOW: i16 = 6;
-const WINDOW_DEPTH_FACTOR: i16 = 4;
 const NEG_INF: i16 = -9999;
 const INF: i16 = 9999;
-const WIN: i16 = 5000;
-const FUTILITY_CUTOFF: [i16; 3] = [8, 15, 25];
+/// Score for the side to move when it can capture the enemy town
+pub const WIN: i16 = 5000;
+const MAX_PLY: usize = 256;
+/// Depth used when a search is only limited by nodes
+const MAX_DEPTH: u8 = 64;
+/// Nodes between two looks at the clock while searching with a time limit
+const TIME_CHECK_INTERVAL: u64 = 1024;
+/// Iterations at least this deep search in the narrowest aspiration window
+const WINDOW_DEPTH: u8 = 8;
+
+// TODO find good values
+define_params! {
+    /// Search constants that can be changed without recompiling.
+    SearchParams {
+        quiescence_depth: u8 = 14,
+        /// Half width of the aspiration window
+        window: i16 = 6,
+        /// Widens the aspiration window for every ply the last iteration was shallower than `WINDOW_DEPTH`
+        window_depth_factor: i16 = 4,
+        futility_cutoff_1: i16 = 8,
+        futility_cutoff_2: i16 = 15,
+        futility_cutoff_3: i16 = 25,
+        /// Plies added when a move threatens the enemy town, the answer is never searched deeper
+        /// than the node before it
+        threat_extension: u8 = 1,
+        /// Margin over a won soldier below which the quiescence search skips a capture
+        delta_margin: i16 = 8,
+        /// First plies of the quiescence search that also try quiet moves threatening the town
+        quiescence_threat_plies: u8 = 1,
+    }
+}
+
+define_params! {
+    /// Switches for every pruning, extension and ordering technique, so each one can be
+    /// compared in matches without a separate build. Their margins are in `SearchParams`.
+    SearchOptions {
+        /// Drops into the quiescence search near the leaves when the eval is far below alpha
+        futility_pruning: bool = true,
+        /// Searches the answer to a town threat `threat_extension` plies deeper
+        threat_extensions: bool = true,
+        /// Searches every iteration in a window around the last score
+        aspiration_windows: bool = true,
+        /// Ends the search of a node with a TT entry that is deep enough
+        tt_cutoffs: bool = true,
+        /// Tries quiet moves that caused cutoffs at the same height first
+        killer_moves: bool = true,
+        /// Lets the quiescence search keep the eval instead of capturing, unless the town is threatened
+        stand_pat: bool = true,
+        /// Skips captures in the quiescence search that cannot raise the eval to alpha
+        delta_pruning: bool = true,
+        /// Skips captures in the quiescence search that lose soldiers in the exchange on their square
+        see_pruning: bool = true,
+        /// Searches quiet moves that threaten the town in the first quiescence plies
+        quiescence_threats: bool = true,
+    }
+}
+
+impl SearchParams {
+    /// Futility margin for the remaining `depth`, only depths 1 to 3 are pruned.
+    pub fn futility_cutoff(&self, depth: u8) -> i16 {
+        match depth {
+            1 => self.futility_cutoff_1,
+            2 => self.futility_cutoff_2,
+            _ => self.futility_cutoff_3,
+        }
+    }
+}
+
+/// When to stop a search. The node and time limits are checked while searching, an
+/// iteration that exceeds them is thrown away for the best move of the last one.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SearchLimits {
+    pub depth: u8,
+    pub nodes: Option<u64>,
+    pub time: Option<Duration>,
+}
+
+impl SearchLimits {
+    pub fn depth(depth: u8) -> Self {
+        Self {
+            depth,
+            nodes: None,
+            time: None,
+        }
+    }
+
+    pub fn nodes(nodes: u64) -> Self {
+        Self {
+            depth: MAX_DEPTH,
+            nodes: Some(nodes),
+            time: None,
+        }
+    }
+
+    pub fn time(time: Duration) -> Self {
+        Self {
+            depth: MAX_DEPTH,
+            nodes: None,
+            time: Some(time),
+        }
+    }
+
+    /// Whether a search that started at `start` and searched `nodes` has to stop.
+    pub fn exceeded(&self, start: Instant, nodes: u64) -> bool {
+        self.nodes.is_some_and(|limit| nodes >= limit)
+            || self.time.is_some_and(|limit| start.elapsed() >= limit)
+    }
+}
+
+/// Work of one iteration of the iterative deepening.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct IterationStats {
+    pub depth: u8,
+    pub nodes: u64,
+}
+
+impl IterationStats {
+    /// The branching factor a tree of this depth and size would have if it were uniform.
+    pub fn branching_factor(&self) -> f64 {
+        (self.nodes.max(1) as f64).powf(1.0 / self.depth.max(1) as f64)
+    }
+}
+
+/// Counters of a search, to see how well the pruning and move ordering work.
+/// Beta cutoffs and futility prunes are counted in the main search, stand pat cutoffs,
+/// delta and SEE prunes in the quiescence search and the TT counters in both.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SearchStats {
+    pub nodes: u64,
+    pub quiescence_nodes: u64,
+    pub tt_probes: u64,
+    pub tt_hits: u64,
+    pub tt_cutoffs: u64,
+    pub beta_cutoffs: u64,
+    /// Beta cutoffs by the first move searched
+    pub first_move_cutoffs: u64,
+    pub futility_prunes: u64,
+    pub stand_pat_cutoffs: u64,
+    pub delta_prunes: u64,
+    pub see_prunes: u64,
+    /// Re-searches of a depth after a failed aspiration window add to its iteration
+    pub iterations: Vec<IterationStats>,
+}
+
+impl SearchStats {
+    fn add_iteration(&mut self, depth: u8, nodes: u64) {
+        match self.iterations.last_mut() {
+            Some(last) if last.depth == depth => last.nodes += nodes,
+            _ => self.iterations.push(IterationStats { depth, nodes }),
+        }
+    }
+}
+
+fn percent(part: u64, total: u64) -> f64 {
+    100.0 * part as f64 / total.max(1) as f64
+}
+
+impl fmt::Display for SearchStats {
+    /// One line per iteration followed by the totals.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for iteration in self.iterations.iter() {
+            writeln!(
+                f,
+                "depth {} nodes {} ebf {:.2}",
+                iteration.depth,
+                iteration.nodes,
+                iteration.branching_factor()
+            )?;
+        }
+        write!(
+            f,
+            "nodes {} qnodes {} tt_probes {} tt_hits {} ({:.1}%) tt_cutoffs {} \
+             beta_cutoffs {} first_move {} ({:.1}%) futility {} stand_pat {} delta {} see {}",
+            self.nodes,
+            self.quiescence_nodes,
+            self.tt_probes,
+            self.tt_hits,
+            percent(self.tt_hits, self.tt_probes),
+            self.tt_cutoffs,
+            self.beta_cutoffs,
+            self.first_move_cutoffs,
+            percent(self.first_move_cutoffs, self.beta_cutoffs),
+            self.futility_prunes,
+            self.stand_pat_cutoffs,
+            self.delta_prunes,
+            self.see_prunes
+        )
+    }
+}
+
+/// The best move of a search with the statistics of finding it.
+#[derive(Clone)]
+pub struct SearchResult {
+    pub best: MoveWithScore,
+    pub stats: SearchStats,
+}
+
+/// Sets whichever of the search parameters, search options or evaluation parameters has
+/// the name, shared by everything that keeps them for a searcher.
+pub fn set_search_option(
+    params: &mut SearchParams,
+    options: &mut SearchOptions,
+    eval_params: &mut EvalParams,
+    name: &str,
+    value: &str,
+) -> Result<(), ParamsError> {
+    if SearchParams::names().contains(&name) {
+        params.set(name, value)
+    } else if SearchOptions::names().contains(&name) {
+        options.set(name, value)
+    } else {
+        eval_params.set(name, value)
+    }
+}
 
 pub struct Searcher {
     pub tt: TTable,
+    pub params: SearchParams,
+    pub options: SearchOptions,
+    pub eval_params: EvalParams,
+    /// Prints the progress of every iteration
+    pub verbose: bool,
+    /// Endgame tables probed below the root
+    pub tablebase: Option<Arc<Tablebase>>,
+    /// Opening book played from before searching
+    pub book: Option<Arc<Book>>,
+    /// Replaces the handcrafted evaluation
+    #[cfg(feature = "nnue")]
+    pub network: Option<Arc<Network>>,
+    book_rng: Rng,
+    /// Largest random change of the eval, for weaker play
+    pub eval_noise: i16,
+    /// Makes the noise of a position differ between searchers
+    pub noise_seed: u64,
+    /// Root moves left out of the search, for multi-PV
+    root_excluded: Vec<BitMove>,
+    stats: SearchStats,
+    tt_size: usize,
+    killers: [[BitMove; 2]; MAX_PLY],
+    /// Limits of the running search
+    limits: SearchLimits,
+    start: Instant,
+    /// Nodes visited by the running search
+    visited: u64,
+    /// Set once a limit is exceeded, every node then returns at once
+    aborted: bool,
+}
+
+impl Default for Searcher {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Searcher {
     pub fn new() -> Self {
+        Self::with_tt_size(TT_SIZE)
+    }
+
+    /// A searcher with a smaller table, for running many searchers at once.
+    pub fn with_tt_size(tt_size: usize) -> Self {
         let mut table = TTable::default();
-        table.allocate(TT_SIZE);
-        Self { tt: table }
+        table.allocate(tt_size);
+        Self {
+            tt: table,
+            params: SearchParams::default(),
+            options: SearchOptions::default(),
+            eval_params: EvalParams::default(),
+            verbose: true,
+            tablebase: None,
+            book: None,
+            #[cfg(feature = "nnue")]
+            network: None,
+            book_rng: Rng::new(0),
+            eval_noise: 0,
+            noise_seed: 0,
+            root_excluded: Vec::new(),
+            stats: SearchStats::default(),
+            tt_size,
+            killers: [[BitMove::null(); 2]; MAX_PLY],
+            limits: SearchLimits::depth(MAX_DEPTH),
+            start: Instant::now(),
+            visited: 0,
+            aborted: false,
+        }
+    }
+
+    /// Forgets the table entries and killer moves of earlier searches.
+    pub fn clear(&mut self) {
+        self.tt.allocate(self.tt_size);
+        self.killers = [[BitMove::null(); 2]; MAX_PLY];
+    }
+
+    /// Sets a search parameter, search option or evaluation parameter by name,
+    /// used for engine options.
+    pub fn set_option(&mut self, name: &str, value: &str) -> Result<(), ParamsError> {
+        set_search_option(
+            &mut self.params,
+            &mut self.options,
+            &mut self.eval_params,
+            name,
+            value,
+        )
     }
+
+    /// Loads a file that can mix search and evaluation parameters.
+    pub fn load_params<P: AsRef<Path>>(&mut self, path: P) -> Result<(), ParamsError> {
+        let input = std::fs::read_to_string(path)?;
+        for (name, value) in parse_lines(&input)? {
+            self.set_option(name, value)?;
+        }
+        Ok(())
+    }
+
+    /// The eval with the noise of the position, the same position always gets the same noise
+    /// so transpositions agree.
+    fn evaluate(&self, board: &Board) -> i16 {
+        #[cfg(feature = "nnue")]
+        let score = eval_with_network(board, &self.eval_params, self.network.as_ref());
+        #[cfg(not(feature = "nnue"))]
+        let score = eval_with(board, &self.eval_params);
+        if self.eval_noise <= 0 {
+            return score;
+        }
+        score + Rng::new(board.hash() ^ self.noise_seed).symmetric(self.eval_noise)
+    }
+
+    /// The best `count` root moves, each searched with the limits while the ones before are
+    /// left out. The book is not used.
+    pub fn search_multi_pv(
+        &mut self,
+        board: &mut Board,
+        limits: &SearchLimits,
+        count: usize,
+    ) -> Vec<MoveWithScore> {
+        let book = self.book.take();
+        let mut lines = Vec::new();
+        while lines.len() < count {
+            let m = self.search_with_limits(board, limits);
+            if m.bitmove() == BitMove::null() {
+                break;
+            }
+            self.root_excluded.push(m.bitmove());
+            lines.push(m);
+        }
+        self.root_excluded.clear();
+        self.book = book;
+        lines
+    }
+
     // TODO futility pruning
-    // TODO delta pruning
-    // TODO static exchange evaluation (this should be quite important for this game)
     pub fn search(&mut self, board: &mut Board, max_depth: u8) -> MoveWithScore {
-        let mut current_depth = 4;
+        self.search_with_limits(board, &SearchLimits::depth(max_depth))
+    }
+
+    pub fn search_with_limits(
+        &mut self,
+        board: &mut Board,
+        limits: &SearchLimits,
+    ) -> MoveWithScore {
+        self.search_with_stats(board, limits).best
+    }
+
+    pub fn search_with_stats(&mut self, board: &mut Board, limits: &SearchLimits) -> SearchResult {
+        self.stats = SearchStats::default();
+        if let Some(book) = &self.book {
+            if let Some(m) = book.choose(board, &mut self.book_rng) {
+                // book moves are not searched, so they come without a score
+                return SearchResult {
+                    best: MoveWithScore::new_with_score(m, 0),
+                    stats: std::mem::take(&mut self.stats),
+                };
+            }
+        }
+        let max_depth = limits.depth;
+        let mut current_depth = max_depth.min(4);
+        self.limits = *limits;
+        self.start = Instant::now();
+        self.visited = 0;
+        self.aborted = false;
         let mut alpha = NEG_INF;
         let mut beta = INF;
         let mut best_move = MoveWithScore::new_with_score(BitMove::null(), alpha);
 
+        self.killers = [[BitMove::null(); 2]; MAX_PLY];
         while current_depth <= max_depth {
-            self.tt.allocate(TT_SIZE);
-            println!("Searching at {}", current_depth);
+            self.tt.allocate(self.tt_size);
+            if self.verbose {
+                println!("Searching at {}", current_depth);
+            }
             let mut b = board.shallow_clone();
-            let (m, n) = self.alpha_beta_search(&mut b, alpha, beta, current_depth, 0, 0);
-            println!("Nodes searched {}", n);
+            // the network inputs are then updated with every move instead of refreshed at every eval
+            #[cfg(feature = "nnue")]
+            if let Some(network) = &self.network {
+                b.set_network(Arc::clone(network));
+            }
+            let visited = self.visited;
+            let (m, _) = self.alpha_beta_search(&mut b, alpha, beta, current_depth, 0, 0);
+            let n = self.visited - visited;
+            self.stats.add_iteration(current_depth, n);
+            if self.verbose {
+                println!("Nodes searched {}", n);
+            }
+            if self.aborted {
+                // the root moves searched before the abort are still better than nothing
+                if best_move.bitmove() == BitMove::null() {
+                    best_move = m;
+                }
+                break;
+            }
             if m.score <= alpha {
                 alpha = NEG_INF;
             } else if m.score >= beta {
                 beta = INF;
             } else {
                 if m.bitmove() != BitMove::null() {
-                    alpha = m.score
-                        - (WINDOW + (max_depth - current_depth) as i16 * WINDOW_DEPTH_FACTOR);
-                    beta = m.score
-                        + (WINDOW + (max_depth - current_depth) as i16 * WINDOW_DEPTH_FACTOR);
+                    if self.options.aspiration_windows {
+                        let window = self.aspiration_window(current_depth);
+                        alpha = m.score - window;
+                        beta = m.score + window;
+                    }
                     best_move = m;
-                    println!(
-                        "Best move {}, score {} alpha {}, beta, {}",
-                        m.bitmove(),
-                        m.score(),
-                        alpha,
-                        beta
-                    );
+                    if self.verbose {
+                        println!(
+                            "Best move {}, score {} alpha {}, beta, {}",
+                            m.bitmove(),
+                            m.score(),
+                            alpha,
+                            beta
+                        );
+                    }
                 }
                 current_depth += 2;
             }
+            if limits.exceeded(self.start, self.visited) {
+                break;
+            }
+        }
+        if best_move.bitmove() == BitMove::null() {
+            // aborted before a single root move was searched
+            if let Some(m) = board.generate_moves().next() {
+                best_move = MoveWithScore::new_with_score(m, 0);
+            }
+        }
+        self.stats.nodes = self.visited;
+        SearchResult {
+            best: best_move,
+            stats: std::mem::take(&mut self.stats),
         }
-        best_move
     }
     pub fn alpha_beta_search(
         &mut self,
@@ -72,61 +482,113 @@ impl Searcher {
         nodes: u64,
     ) -> (MoveWithScore, u64) {
         let original_alpha = alpha;
+        if depth == 0 && board.last_capture() {
+            return self.quiescence(board, alpha, beta, self.params.quiescence_depth, 0);
+        }
+        if self.out_of_limits() {
+            return (MoveWithScore::new_with_score(BitMove::null(), 0), 0);
+        }
         if depth == 0 {
-            if board.last_capture() {
-                return self.quiescence(board, alpha, beta, QUIESCENCE_DEPTH, 0);
-            }
             return (
-                MoveWithScore::new_with_score(BitMove::null(), eval(board)),
+                MoveWithScore::new_with_score(BitMove::null(), self.evaluate(board)),
                 1,
             );
         }
         if height != 0 {
-            if let Some(entry) = self.tt.get(board.hash()) {
-                let score = entry.score();
-                if entry.depth >= depth && (entry.is_lower() && score >= beta)
+            if let Some(result) = self.tablebase.as_ref().and_then(|tb| tb.probe(board)) {
+                return (
+                    MoveWithScore::new_with_score(BitMove::null(), result.score(WIN)),
+                    1,
+                );
+            }
+        }
+        let mut tt_move = BitMove::null();
+        self.stats.tt_probes += 1;
+        if let Some(entry) = self.tt.get(board.hash()) {
+            self.stats.tt_hits += 1;
+            let score = entry.score();
+            if height != 0
+                && self.options.tt_cutoffs
+                && !entry.quiescence
+                && entry.depth >= depth
+                && ((entry.is_lower() && score >= beta)
                     || (entry.is_upper() && score <= alpha)
-                    || (entry.is_exact())
-                {
-                    return (MoveWithScore::new_with_score(entry.mv, score), 1);
-                }
+                    || entry.is_exact())
+            {
+                self.stats.tt_cutoffs += 1;
+                return (MoveWithScore::new_with_score(entry.mv, score), 1);
             }
+            tt_move = entry.mv;
         }
-        if depth <= 3 && !board.last_capture() {
+        if self.options.futility_pruning && depth <= 3 && !board.last_capture() {
             assert!(depth != 0);
-            let eval = eval(board);
-            if eval + FUTILITY_CUTOFF[depth as usize - 1] < alpha {
-                return self.quiescence(board, alpha, beta, QUIESCENCE_DEPTH, 0);
+            let eval = self.evaluate(board);
+            if eval + self.params.futility_cutoff(depth) < alpha {
+                self.stats.futility_prunes += 1;
+                return self.quiescence(board, alpha, beta, self.params.quiescence_depth, 0);
             }
         }
 
-        let moves = board.generate_moves();
-
-        if moves.is_empty() {
-            return (MoveWithScore::new_with_score(BitMove::null(), -5000), 1);
-        }
+        let ply = (height as usize).min(MAX_PLY - 1);
+        let killers = if self.options.killer_moves {
+            self.killers[ply]
+        } else {
+            [BitMove::null(); 2]
+        };
+        let mut picker = MovePicker::new(board, tt_move, killers);
+        let mut move_count = 0;
 
         let mut best_move = MoveWithScore::new_with_score(BitMove::null(), alpha);
         let mut new_nodes = nodes;
-        for m in moves {
-            if m.dst() == board.enemy_castle().to_square() {
+        while let Some(m) = picker.next(board) {
+            if height == 0 && self.root_excluded.contains(&m) {
+                continue;
+            }
+            move_count += 1;
+            if board.captures_town(m) {
                 return (MoveWithScore::new_with_score(m, WIN), new_nodes);
             }
             board.apply_move(m);
-            let (ret, n) = self.alpha_beta_search(board, -beta, -alpha, depth - 1, height + 1, 0);
+            // a threatened town is the equivalent of check, so the answer is searched a ply deeper,
+            // until the line is twice as long as the depth left so chains of threats end
+            let extension = if self.options.threat_extensions
+                && height / 2 < depth
+                && board.town_threatened(board.side_to_move())
+            {
+                self.params.threat_extension
+            } else {
+                0
+            };
+            let child_depth = (depth - 1).saturating_add(extension).min(depth);
+            let (ret, n) = self.alpha_beta_search(board, -beta, -alpha, child_depth, height + 1, 0);
             new_nodes += n;
             let score = -ret.score();
             let result = MoveWithScore::new_with_score(m, score);
             board.undo_move();
+            if self.aborted {
+                return (best_move, new_nodes);
+            }
             if result.score > alpha {
                 alpha = result.score;
                 if alpha >= beta {
+                    self.stats.beta_cutoffs += 1;
+                    if move_count == 1 {
+                        self.stats.first_move_cutoffs += 1;
+                    }
+                    if !m.is_capture() && self.killers[ply][0] != m {
+                        self.killers[ply][1] = self.killers[ply][0];
+                        self.killers[ply][0] = m;
+                    }
                     return (result, new_nodes);
                 }
                 best_move = result;
             }
         }
 
+        if move_count == 0 {
+            return (MoveWithScore::new_with_score(BitMove::null(), -WIN), 1);
+        }
+
         let flag = if best_move.score >= beta {
             FLAG_LOWER
         } else if best_move.score <= original_alpha {
@@ -140,6 +602,8 @@ impl Searcher {
         (best_move, new_nodes)
     }
 
+    /// Searches captures, shots and town threats until the position is quiet. The side to move
+    /// can stand pat on the eval unless its town is threatened, then every move is searched.
     pub fn quiescence(
         &mut self,
         board: &mut Board,
@@ -148,44 +612,66 @@ impl Searcher {
         depth: u8,
         nodes: u64,
     ) -> (MoveWithScore, u64) {
+        self.stats.quiescence_nodes += 1;
+        if self.out_of_limits() {
+            return (MoveWithScore::new_with_score(BitMove::null(), 0), 0);
+        }
         let original_alpha = alpha;
+        let eval = self.evaluate(board);
         if depth == 0 {
-            return (
-                MoveWithScore::new_with_score(BitMove::null(), eval(board)),
-                1,
-            );
+            return (MoveWithScore::new_with_score(BitMove::null(), eval), 1);
         }
+        let mut tt_move = BitMove::null();
+        self.stats.tt_probes += 1;
         if let Some(entry) = self.tt.get(board.hash()) {
+            self.stats.tt_hits += 1;
             let score = entry.score();
-            if entry.depth >= depth && (entry.is_lower() && score >= beta)
-                || (entry.is_upper() && score <= alpha)
-                || (entry.is_exact())
+            // only quiescence entries with at least as much quiescence depth left end the node,
+            // they also searched the town threats of every ply this one would
+            if self.options.tt_cutoffs
+                && entry.quiescence
+                && entry.depth >= depth
+                && ((entry.is_lower() && score >= beta)
+                    || (entry.is_upper() && score <= alpha)
+                    || entry.is_exact())
             {
+                self.stats.tt_cutoffs += 1;
                 return (MoveWithScore::new_with_score(entry.mv, score), 1);
             }
+            tt_move = entry.mv;
         }
 
-        let moves = board.generate_captures();
-
-        if moves.is_empty() {
-            return (
-                MoveWithScore::new_with_score(BitMove::null(), eval(board)),
-                1,
-            );
+        let threatened = board.town_threatened(board.side_to_move());
+        let stand_pat = self.options.stand_pat && !threatened;
+        if stand_pat {
+            if eval >= beta {
+                self.stats.stand_pat_cutoffs += 1;
+                return (MoveWithScore::new_with_score(BitMove::null(), eval), 1);
+            }
+            alpha = alpha.max(eval);
         }
 
+        let moves = self.quiescence_moves(board, tt_move, threatened, depth);
         let mut best_move = MoveWithScore::new_with_score(BitMove::null(), alpha);
         let mut new_nodes = nodes;
+        let mut move_count = 0;
         for m in moves {
-            if m.dst() == board.enemy_castle().to_square() {
+            if board.captures_town(m) {
                 return (MoveWithScore::new_with_score(m, WIN), new_nodes);
             }
+            if stand_pat && m.is_capture() && self.prune_capture(board, m, eval, alpha) {
+                continue;
+            }
+            move_count += 1;
             board.apply_move(m);
             let (ret, n) = self.quiescence(board, -beta, -alpha, depth - 1, 0);
             new_nodes += n;
             let score = -ret.score();
             let result = MoveWithScore::new_with_score(m, score);
             board.undo_move();
+            if self.aborted {
+                return (best_move, new_nodes);
+            }
             if result.score > alpha {
                 alpha = result.score;
                 if alpha >= beta {
@@ -194,6 +680,15 @@ impl Searcher {
                 best_move = result;
             }
         }
+        if move_count == 0 && !stand_pat {
+            // a threatened side without moves has lost like in the main search
+            let score = if threatened && board.generate_moves().is_empty() {
+                -WIN
+            } else {
+                eval
+            };
+            return (MoveWithScore::new_with_score(BitMove::null(), score), 1);
+        }
         let flag = if best_move.score >= beta {
             FLAG_LOWER
         } else if best_move.score <= original_alpha {
@@ -203,8 +698,256 @@ impl Searcher {
         };
 
         self.tt
-            .insert(board, best_move.score, best_move.bitmove(), flag, depth);
+            .insert_quiescence(board, best_move.score, best_move.bitmove(), flag, depth);
 
         (best_move, new_nodes)
     }
+
+    /// Half width of the window around the score of an iteration of `depth`. Shallow scores
+    /// change more between iterations, so their windows are wider.
+    fn aspiration_window(&self, depth: u8) -> i16 {
+        self.params.window
+            + WINDOW_DEPTH.saturating_sub(depth) as i16 * self.params.window_depth_factor
+    }
+
+    /// Counts a node and whether the search has to stop. The clock is only read
+    /// every `TIME_CHECK_INTERVAL` nodes.
+    fn out_of_limits(&mut self) -> bool {
+        self.visited += 1;
+        if !self.aborted {
+            self.aborted = self.limits.nodes.is_some_and(|limit| self.visited > limit)
+                || (self.visited.is_multiple_of(TIME_CHECK_INTERVAL)
+                    && self
+                        .limits
+                        .time
+                        .is_some_and(|limit| self.start.elapsed() >= limit));
+        }
+        self.aborted
+    }
+
+    /// Every move if the town is threatened, otherwise the captures and shots
+    /// and in the first plies the quiet moves that threaten the enemy town.
+    fn quiescence_moves(
+        &self,
+        board: &mut Board,
+        tt_move: BitMove,
+        threatened: bool,
+        depth: u8,
+    ) -> Vec<BitMove> {
+        let mut picker = if threatened {
+            MovePicker::new(board, tt_move, [BitMove::null(); 2])
+        } else {
+            MovePicker::new_captures(board)
+        };
+        let mut moves = Vec::new();
+        while let Some(m) = picker.next(board) {
+            moves.push(m);
+        }
+        if !threatened
+            && self.options.quiescence_threats
+            && self.params.quiescence_depth.saturating_sub(depth)
+                < self.params.quiescence_threat_plies
+        {
+            for m in board.generate_moves().filter(|m| !m.is_capture()) {
+                if Self::threatens_town(board, m) {
+                    moves.push(m);
+                }
+            }
+        }
+        moves
+    }
+
+    /// Whether the capture `m` is skipped by delta or SEE pruning, captures that threaten
+    /// the town are always searched.
+    fn prune_capture(&mut self, board: &mut Board, m: BitMove, eval: i16, alpha: i16) -> bool {
+        let delta = self.options.delta_pruning
+            && eval + self.eval_params.piece_value + self.params.delta_margin <= alpha;
+        let losing = !delta && self.options.see_pruning && see(board, m) < 0;
+        if !(delta || losing) || Self::threatens_town(board, m) {
+            return false;
+        }
+        if delta {
+            self.stats.delta_prunes += 1;
+        } else {
+            self.stats.see_prunes += 1;
+        }
+        true
+    }
+
+    fn threatens_town(board: &mut Board, m: BitMove) -> bool {
+        board.apply_move(m);
+        let threat = board.town_threatened(board.side_to_move());
+        board.undo_move();
+        threat
+    }
+}
+
+#[test]
+fn search_stats_test() {
+    let mut searcher = Searcher::with_tt_size(1 << 12);
+    searcher.verbose = false;
+    let result = searcher.search_with_stats(&mut Board::start_position(), &SearchLimits::depth(6));
+    let stats = result.stats;
+    assert_eq!(
+        stats.iterations.iter().map(|i| i.depth).collect::<Vec<_>>(),
+        vec![4, 6]
+    );
+    assert_eq!(
+        stats.nodes,
+        stats.iterations.iter().map(|i| i.nodes).sum::<u64>()
+    );
+    assert!(stats.tt_hits <= stats.tt_probes && stats.tt_cutoffs <= stats.tt_hits);
+    assert!(stats.first_move_cutoffs <= stats.beta_cutoffs && stats.beta_cutoffs > 0);
+    assert!(stats.iterations[1].branching_factor() > 1.0);
+    // a new search starts counting from zero
+    let again = searcher.search_with_stats(&mut Board::start_position(), &SearchLimits::depth(4));
+    assert!(again.stats.nodes < stats.nodes);
+
+    // switched off techniques leave their counters at zero
+    searcher.set_option("futility_pruning", "false").unwrap();
+    searcher.set_option("tt_cutoffs", "false").unwrap();
+    assert!(searcher.set_option("tt_cutoffs", "1").is_err());
+    let off = searcher.search_with_stats(&mut Board::start_position(), &SearchLimits::depth(4));
+    assert_eq!((off.stats.futility_prunes, off.stats.tt_cutoffs), (0, 0));
+    assert!(again.stats.futility_prunes > 0);
+    assert!(again.stats.stand_pat_cutoffs > 0);
+    for option in ["stand_pat", "delta_pruning", "see_pruning"] {
+        searcher.set_option(option, "false").unwrap();
+    }
+    let off = searcher.search_with_stats(&mut Board::start_position(), &SearchLimits::depth(4));
+    assert_eq!(
+        (
+            off.stats.stand_pat_cutoffs,
+            off.stats.delta_prunes,
+            off.stats.see_prunes
+        ),
+        (0, 0, 0)
+    );
+    let options = SearchOptions::parse("killer_moves = false").unwrap();
+    assert!(!options.killer_moves && options.aspiration_windows);
+}
+
+#[test]
+fn threat_extension_test() {
+    // white soldiers a step away from threatening the black town
+    let fen = "3B4/1b5b/2w1w1b1/1b6/8/1b1w2w1/w1w3w1/6W1 w";
+    let nodes = |extension: &str| {
+        let mut searcher = Searcher::with_tt_size(1 << 12);
+        searcher.verbose = false;
+        searcher.set_option("threat_extension", extension).unwrap();
+        let mut board = Board::from_fen(fen).unwrap();
+        searcher
+            .search_with_stats(&mut board, &SearchLimits::depth(4))
+            .stats
+            .nodes
+    };
+    // extensions never search deeper than the node before, so a large one ends like one ply
+    assert_eq!(nodes("255"), nodes("1"));
+    assert_ne!(nodes("1"), nodes("0"));
+}
+
+#[test]
+fn quiescence_test() {
+    let mut searcher = Searcher::with_tt_size(1 << 12);
+    let quiesce = |searcher: &mut Searcher, fen: &str| {
+        let mut board = Board::from_fen(fen).unwrap();
+        let depth = searcher.params.quiescence_depth;
+        searcher.tt.allocate(1 << 12);
+        searcher
+            .quiescence(&mut board, NEG_INF, INF, depth, 0)
+            .0
+            .score()
+    };
+    // a quiet position stands pat on the eval
+    let quiet = "B7/8/8/8/8/w7/8/7W w";
+    assert_eq!(
+        quiesce(&mut searcher, quiet),
+        searcher.evaluate(&Board::from_fen(quiet).unwrap())
+    );
+    // a threatened town can not stand pat
+    assert_eq!(quiesce(&mut searcher, "B7/8/8/8/8/w7/7b/7W w"), -WIN);
+    // a quiet move threatening an undefended town is found
+    assert_eq!(quiesce(&mut searcher, "B7/8/w7/8/8/8/8/7W w"), WIN);
+    // threats are tried on every quiescence ply when the setting covers all of them
+    searcher.params.quiescence_threat_plies = u8::MAX;
+    assert_eq!(quiesce(&mut searcher, "B7/8/w7/8/8/8/8/7W w"), WIN);
+    searcher.params.quiescence_threat_plies = 1;
+    searcher.options.quiescence_threats = false;
+    assert!(quiesce(&mut searcher, "B7/8/w7/8/8/8/8/7W w") < WIN);
+
+    // an even trade is not a losing capture, so it is searched
+    searcher.options.delta_pruning = false;
+    searcher.stats = SearchStats::default();
+    quiesce(&mut searcher, "B7/8/3b4/3b4/3w4/8/8/7W w");
+    assert_eq!(searcher.stats.see_prunes, 0);
+    assert!(searcher.stats.quiescence_nodes > 1);
+    searcher.options.delta_pruning = true;
+
+    // an entry of a shallower search does not end a deeper one
+    let mut board = Board::from_fen("B7/8/8/3b4/3w4/8/8/7W w").unwrap();
+    searcher.tt.allocate(1 << 12);
+    searcher
+        .tt
+        .insert(&board, 1234, BitMove::null(), FLAG_EXACT, 1);
+    let (m, _) = searcher.alpha_beta_search(&mut board, NEG_INF, INF, 3, 1, 0);
+    assert_ne!(m.score(), 1234);
+    // the same holds for quiescence entries stored with less quiescence depth left,
+    // while one with enough depth ends the node
+    let depth = searcher.params.quiescence_depth;
+    for (stored, cut) in [(depth - 1, false), (depth, true)] {
+        searcher.tt.allocate(1 << 12);
+        searcher
+            .tt
+            .insert_quiescence(&board, 1234, BitMove::null(), FLAG_EXACT, stored);
+        let score = searcher
+            .quiescence(&mut board, NEG_INF, INF, depth, 0)
+            .0
+            .score();
+        assert_eq!(score == 1234, cut);
+    }
+    // and quiescence entries never end the main search
+    searcher.tt.allocate(1 << 12);
+    searcher
+        .tt
+        .insert_quiescence(&board, 1234, BitMove::null(), FLAG_EXACT, 14);
+    let (m, _) = searcher.alpha_beta_search(&mut board, NEG_INF, INF, 3, 1, 0);
+    assert_ne!(m.score(), 1234);
+}
+
+#[test]
+fn search_limits_test() {
+    let mut searcher = Searcher::with_tt_size(1 << 12);
+    searcher.verbose = false;
+    let mut board = Board::start_position();
+    let result = searcher.search_with_stats(&mut board, &SearchLimits::nodes(10_000));
+    // the limit stops the search inside the second iteration, which still counts its nodes
+    assert!(result.stats.nodes <= 10_001);
+    assert_eq!(result.stats.iterations.len(), 2);
+    // the unfinished iteration is thrown away for the move of the first one
+    let full = searcher.search_with_stats(&mut board, &SearchLimits::depth(4));
+    assert_eq!(result.best.bitmove(), full.best.bitmove());
+
+    // a limit hit before any root move was searched still plays a legal move
+    let result = searcher.search_with_stats(&mut board, &SearchLimits::nodes(1));
+    assert!(board.generate_moves().contains(result.best.bitmove()));
+    let result = searcher.search_with_stats(&mut board, &SearchLimits::time(Duration::ZERO));
+    assert!(board.generate_moves().contains(result.best.bitmove()));
+}
+
+#[test]
+fn aspiration_window_test() {
+    let searcher = Searcher::with_tt_size(1 << 12);
+    // the window only depends on the iteration, not on how far the search may go
+    assert_eq!(
+        searcher.aspiration_window(4),
+        searcher.params.window + 4 * searcher.params.window_depth_factor
+    );
+    assert_eq!(
+        searcher.aspiration_window(WINDOW_DEPTH),
+        searcher.params.window
+    );
+    assert_eq!(
+        searcher.aspiration_window(MAX_DEPTH),
+        searcher.params.window
+    );
 }
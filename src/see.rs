@@ -0,0 +1,55 @@
+use crate::board::Board;
+use crate::cannon_move::BitMove;
+use crate::movegen::MoveGen;
+use crate::square::Square;
+
+/// Static exchange evaluation: soldiers the side to move wins with the capture `m`, less
+/// the soldiers it loses, once both sides have played out the best captures on its square.
+/// Every soldier is worth the same, so a shot always wins one and a defended capture trades even.
+pub fn see(board: &mut Board, m: BitMove) -> i16 {
+    debug_assert!(m.is_capture());
+    if m.is_shot() {
+        return 1;
+    }
+    board.apply_move(m);
+    let gain = 1 - recapture(board, m.dst());
+    board.undo_move();
+    gain
+}
+
+/// Soldiers the side to move wins by capturing on `sq`, it can also leave the square alone.
+fn recapture(board: &mut Board, sq: Square) -> i16 {
+    let mut best = 0;
+    for m in MoveGen::generate_captures(board).filter(|m| m.dst() == sq) {
+        // the shooter stays where it is, so nothing can take back
+        if m.is_shot() {
+            return 1;
+        }
+        board.apply_move(m);
+        best = best.max(1 - recapture(board, sq));
+        board.undo_move();
+        if best == 1 {
+            break;
+        }
+    }
+    best
+}
+
+#[test]
+fn see_test() {
+    use crate::notation::parse_move;
+
+    let see_of = |fen: &str, m: &str| {
+        let mut board = Board::from_fen(fen).unwrap();
+        let m = parse_move(&board, m).unwrap();
+        let before = board.to_fen();
+        let gain = see(&mut board, m);
+        assert_eq!(board.to_fen(), before);
+        gain
+    };
+    // an undefended soldier is won, a defended one is traded
+    assert_eq!(see_of("B7/8/8/3b4/3w4/8/8/7W w", "d4d5"), 1);
+    assert_eq!(see_of("B7/8/3b4/3b4/3w4/8/8/7W w", "d4d5"), 0);
+    // a second attacker would win the recapture back, so the defender leaves it
+    assert_eq!(see_of("B7/8/3b4/3b4/2ww4/8/8/7W w", "d4d5"), 1);
+}
@@ -0,0 +1,228 @@
+use crate::board::Board;
+use crate::cannon_move::BitMove;
+use crate::record::{GameResult, RecordError};
+use crate::rng::Rng;
+use crate::search::{SearchLimits, Searcher};
+use rayon::prelude::*;
+use std::collections::HashSet;
+use std::io::Write;
+use std::path::Path;
+
+/// A searched position, stored as one line of `position;score;result`.
+/// The score is from the side to move, the result from white.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TrainingRecord {
+    pub fen: String,
+    pub score: i16,
+    pub result: GameResult,
+}
+
+impl TrainingRecord {
+    pub fn to_line(&self) -> String {
+        format!("{};{};{}", self.fen, self.score, self.result)
+    }
+
+    pub fn parse_line(line: &str) -> Result<Self, RecordError> {
+        let invalid = || RecordError::InvalidRecord(line.to_string());
+        let mut parts = line.trim().split(';');
+        let (fen, score, result) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(fen), Some(score), Some(result)) => (fen, score, result),
+            _ => return Err(invalid()),
+        };
+        // parse once to reject broken positions early
+        Board::from_fen(fen)?;
+        Ok(Self {
+            fen: fen.to_string(),
+            score: score.parse().map_err(|_| invalid())?,
+            result: result.parse()?,
+        })
+    }
+
+    pub fn board(&self) -> Board {
+        Board::from_fen(&self.fen).expect("training record with invalid position")
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SelfPlayConfig {
+    pub limits: SearchLimits,
+    /// Random moves played from the start position before searching
+    pub random_plies: usize,
+    /// Games are drawn after this many searched moves
+    pub max_plies: usize,
+    pub tt_size: usize,
+}
+
+impl Default for SelfPlayConfig {
+    fn default() -> Self {
+        Self {
+            limits: SearchLimits::depth(6),
+            random_plies: 8,
+            max_plies: 300,
+            tt_size: 1 << 20,
+        }
+    }
+}
+
+/// Plays one game of the searcher against itself from a random opening.
+/// Openings that already decide the game give no records.
+pub fn play_game(
+    searcher: &mut Searcher,
+    config: &SelfPlayConfig,
+    seed: u64,
+) -> Vec<TrainingRecord> {
+    let mut rng = Rng::new(seed);
+    let mut board = Board::start_position();
+    for _ in 0..config.random_plies {
+        let moves = board.generate_moves();
+        if moves.is_empty() {
+            return Vec::new();
+        }
+        let m = moves.get(rng.below(moves.len()));
+        if board.captures_town(m) {
+            return Vec::new();
+        }
+        board.apply_move(m);
+    }
+
+    let mut positions = Vec::new();
+    let result = loop {
+        if positions.len() >= config.max_plies {
+            break GameResult::Draw;
+        }
+        let m = searcher.search_with_limits(&mut board, &config.limits);
+        if m.bitmove() == BitMove::null() {
+            break GameResult::win_for(!board.side_to_move());
+        }
+        positions.push((board.to_fen(), m.score()));
+        let mover = board.side_to_move();
+        let won = board.captures_town(m.bitmove());
+        board.apply_move(m.bitmove());
+        if won {
+            break GameResult::win_for(mover);
+        }
+    };
+    positions
+        .into_iter()
+        .map(|(fen, score)| TrainingRecord { fen, score, result })
+        .collect()
+}
+
+/// Batches in a row without a new position after which `generate` stops.
+pub const MAX_EMPTY_BATCHES: usize = 3;
+
+pub fn read_dataset<P: AsRef<Path>>(path: P) -> Result<Vec<TrainingRecord>, RecordError> {
+    std::fs::read_to_string(path)?
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(TrainingRecord::parse_line)
+        .collect()
+}
+
+/// Plays games in parallel until the dataset at `path` holds `positions` records.
+/// Existing records are kept, so an interrupted run continues where it stopped,
+/// and positions already in the dataset are skipped by hash. Without enough random
+/// plies the games repeat, so it gives up after `MAX_EMPTY_BATCHES` batches in a row
+/// without a new position and returns the smaller count.
+/// `report` is called after every batch with the number of records.
+pub fn generate<P: AsRef<Path>, F: FnMut(usize)>(
+    path: P,
+    positions: usize,
+    config: &SelfPlayConfig,
+    seed: u64,
+    mut report: F,
+) -> Result<usize, RecordError> {
+    let path = path.as_ref();
+    let existing = if path.exists() {
+        read_dataset(path)?
+    } else {
+        Vec::new()
+    };
+    let mut seen: HashSet<u64> = existing.iter().map(|r| r.board().hash()).collect();
+    let mut count = existing.len();
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+
+    // a resumed run derives its seeds from the records so far, so it does not replay the same games
+    let seed = Rng::new(seed ^ count as u64).next_u64();
+    let mut game = 0;
+    let batch = rayon::current_num_threads() as u64 * 2;
+    let mut empty_batches = 0;
+    while count < positions && empty_batches < MAX_EMPTY_BATCHES {
+        let before = count;
+        let games: Vec<Vec<TrainingRecord>> = (game..game + batch)
+            .into_par_iter()
+            .map_init(
+                || {
+                    let mut searcher = Searcher::with_tt_size(config.tt_size);
+                    searcher.verbose = false;
+                    searcher
+                },
+                |searcher, i| play_game(searcher, config, seed ^ i.wrapping_mul(0x9e37_79b9)),
+            )
+            .collect();
+        game += batch;
+
+        for record in games.into_iter().flatten() {
+            if count >= positions {
+                break;
+            }
+            if seen.insert(record.board().hash()) {
+                writeln!(file, "{}", record.to_line())?;
+                count += 1;
+            }
+        }
+        file.flush()?;
+        report(count);
+        empty_batches = if count == before {
+            empty_batches + 1
+        } else {
+            0
+        };
+    }
+    Ok(count)
+}
+
+#[test]
+fn selfplay_test() {
+    use crate::color::Color;
+
+    let config = SelfPlayConfig {
+        limits: SearchLimits::depth(2),
+        random_plies: 4,
+        max_plies: 6,
+        tt_size: 1 << 10,
+    };
+    let mut searcher = Searcher::with_tt_size(config.tt_size);
+    searcher.verbose = false;
+    let records = play_game(&mut searcher, &config, 1);
+    assert!(!records.is_empty() && records.len() <= 6);
+    // the side to move alternates with every searched position
+    let first = records[0].board().side_to_move();
+    let second = records[1].board().side_to_move();
+    assert_eq!(second, !first);
+    assert_eq!(first, Color::White);
+
+    let line = records[0].to_line();
+    assert_eq!(TrainingRecord::parse_line(&line).unwrap(), records[0]);
+    assert!(TrainingRecord::parse_line("8/8 w;1;1-0").is_err());
+
+    let path = std::env::temp_dir().join(format!("selfplay_test_{}.txt", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(generate(&path, 10, &config, 3, |_| {}).unwrap(), 10);
+    // resuming keeps the old records and only adds new positions
+    assert_eq!(generate(&path, 15, &config, 3, |_| {}).unwrap(), 15);
+    let dataset = read_dataset(&path).unwrap();
+    assert_eq!(dataset.len(), 15);
+    let hashes: HashSet<u64> = dataset.iter().map(|r| r.board().hash()).collect();
+    assert_eq!(hashes.len(), 15);
+    // deterministic games without random plies run out of new positions
+    let repeating = SelfPlayConfig {
+        random_plies: 0,
+        ..config
+    };
+    assert!(generate(&path, 1000, &repeating, 3, |_| {}).unwrap() < 1000);
+    std::fs::remove_file(&path).unwrap();
+}
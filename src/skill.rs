@@ -0,0 +1,205 @@
+use crate::board::Board;
+use crate::cannon_move::BitMove;
+use crate::player::Player;
+use crate::rng::Rng;
+use crate::search::{SearchLimits, Searcher};
+use std::fmt;
+use std::str::FromStr;
+
+/// Table size of skill players, their searches are small.
+const SKILL_TT_SIZE: usize = 1 << 18;
+
+/// Named playing strengths, from weakest to strongest.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SkillLevel {
+    Beginner,
+    Casual,
+    Intermediate,
+    Advanced,
+    Master,
+}
+
+pub const ALL_SKILL_LEVELS: [SkillLevel; 5] = [
+    SkillLevel::Beginner,
+    SkillLevel::Casual,
+    SkillLevel::Intermediate,
+    SkillLevel::Advanced,
+    SkillLevel::Master,
+];
+
+/// How a skill level weakens the searcher.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SkillSettings {
+    pub depth: u8,
+    /// Nodes after which a search stops, every line of a multi-PV search gets them
+    pub nodes: u64,
+    /// Largest random change of the eval
+    pub eval_noise: i16,
+    pub quiescence_depth: u8,
+    /// Percent of moves picked among the best lines instead of playing the best one
+    pub suboptimal_chance: usize,
+    /// Lines searched for a suboptimal pick
+    pub multi_pv: usize,
+    /// How much worse than the best line a picked line may score
+    pub margin: i16,
+}
+
+impl SkillLevel {
+    pub fn name(self) -> &'static str {
+        match self {
+            SkillLevel::Beginner => "beginner",
+            SkillLevel::Casual => "casual",
+            SkillLevel::Intermediate => "intermediate",
+            SkillLevel::Advanced => "advanced",
+            SkillLevel::Master => "master",
+        }
+    }
+
+    pub fn settings(self) -> SkillSettings {
+        let (depth, nodes, eval_noise, quiescence_depth, suboptimal_chance, multi_pv, margin) =
+            match self {
+                SkillLevel::Beginner => (2, 1_000, 6, 1, 40, 4, 10),
+                SkillLevel::Casual => (4, 5_000, 4, 3, 25, 3, 6),
+                SkillLevel::Intermediate => (6, 20_000, 2, 6, 12, 3, 4),
+                SkillLevel::Advanced => (8, 100_000, 1, 10, 5, 2, 2),
+                SkillLevel::Master => (10, 500_000, 0, 14, 0, 1, 0),
+            };
+        SkillSettings {
+            depth,
+            nodes,
+            eval_noise,
+            quiescence_depth,
+            suboptimal_chance,
+            multi_pv,
+            margin,
+        }
+    }
+
+    pub fn limits(self) -> SearchLimits {
+        let settings = self.settings();
+        SearchLimits {
+            nodes: Some(settings.nodes),
+            ..SearchLimits::depth(settings.depth)
+        }
+    }
+}
+
+impl fmt::Display for SkillLevel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for SkillLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ALL_SKILL_LEVELS
+            .into_iter()
+            .find(|level| level.name() == s)
+            .ok_or_else(|| format!("unknown skill level {}", s))
+    }
+}
+
+/// A searcher weakened to a skill level. With the same seed it plays the same moves
+/// in the same positions, its own limits replace the ones it is given.
+pub struct SkillPlayer {
+    level: SkillLevel,
+    seed: u64,
+    searcher: Searcher,
+    rng: Rng,
+}
+
+impl SkillPlayer {
+    pub fn new(level: SkillLevel, seed: u64) -> Self {
+        let settings = level.settings();
+        let mut searcher = Searcher::with_tt_size(SKILL_TT_SIZE);
+        searcher.verbose = false;
+        searcher.eval_noise = settings.eval_noise;
+        searcher.noise_seed = seed;
+        searcher.params.quiescence_depth = settings.quiescence_depth;
+        Self {
+            level,
+            seed,
+            searcher,
+            rng: Rng::new(seed),
+        }
+    }
+
+    pub fn level(&self) -> SkillLevel {
+        self.level
+    }
+}
+
+impl Player for SkillPlayer {
+    fn name(&self) -> String {
+        format!("skill:{}", self.level)
+    }
+
+    fn choose_move(&mut self, board: &Board, _limits: &SearchLimits) -> BitMove {
+        let settings = self.level.settings();
+        let limits = self.level.limits();
+        let mut board = board.shallow_clone();
+        if self.rng.below(100) >= settings.suboptimal_chance {
+            return self
+                .searcher
+                .search_with_limits(&mut board, &limits)
+                .bitmove();
+        }
+        let lines = self
+            .searcher
+            .search_multi_pv(&mut board, &limits, settings.multi_pv);
+        let Some(best) = lines.first() else {
+            return BitMove::null();
+        };
+        // never far worse than the best line, so a won town is always taken
+        let candidates: Vec<BitMove> = lines
+            .iter()
+            .filter(|line| line.score() >= best.score() - settings.margin)
+            .map(|line| line.bitmove())
+            .collect();
+        candidates[self.rng.below(candidates.len())]
+    }
+
+    fn new_game(&mut self) {
+        self.rng = Rng::new(self.seed);
+        self.searcher.clear();
+    }
+}
+
+#[test]
+fn skill_test() {
+    use crate::engine_match::play_game;
+
+    assert_eq!("casual".parse::<SkillLevel>(), Ok(SkillLevel::Casual));
+    assert!("nope".parse::<SkillLevel>().is_err());
+    for pair in ALL_SKILL_LEVELS.windows(2) {
+        let (weaker, stronger) = (pair[0].settings(), pair[1].settings());
+        assert!(weaker.nodes < stronger.nodes && weaker.eval_noise >= stronger.eval_noise);
+    }
+
+    // the node limit stops a search before its depth is reached
+    let mut player = SkillPlayer::new(SkillLevel::Intermediate, 1);
+    let result = player.searcher.search_with_stats(
+        &mut Board::start_position(),
+        &SkillLevel::Intermediate.limits(),
+    );
+    assert!(result.stats.nodes <= SkillLevel::Intermediate.settings().nodes + 1);
+
+    // every level takes the town when it can
+    let board = Board::from_fen("B7/w7/8/8/8/8/8/7W w").unwrap();
+    for level in ALL_SKILL_LEVELS {
+        let mut player = SkillPlayer::new(level, 1);
+        let m = player.choose_move(&board, &SearchLimits::depth(1));
+        assert_eq!(m.dst(), board.enemy_castle().to_square(), "{}", level);
+    }
+
+    // the same seed replays the same game
+    let start = Board::start_position();
+    let game = |seed| {
+        let mut white = SkillPlayer::new(SkillLevel::Beginner, seed);
+        let mut black = SkillPlayer::new(SkillLevel::Casual, seed);
+        play_game(&mut white, &mut black, &start, &SearchLimits::depth(1), 12).moves
+    };
+    assert_eq!(game(3), game(3));
+}
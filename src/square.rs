@@ -2,7 +2,7 @@ use crate::color::Color;
 use crate::defs::*;
 use std::fmt;
 
-#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
 #[repr(transparent)]
 pub struct Square(pub u8);
 
@@ -24,7 +24,7 @@ impl Square {
 
     #[inline(always)]
     pub const fn make_square(rank: Rank, file: File) -> Self {
-        Self((rank.to_index() as u8) << 3 ^ (file.to_index() as u8))
+        Self(rank.to_index() << 3 ^ file.to_index())
     }
 
     #[inline(always)]
@@ -55,14 +55,12 @@ impl Square {
     pub fn up(&self) -> Option<Self> {
         self.rank()
             .up()
-            .and_then(|r| Some(Square::make_square(r, self.file())))
+            .map(|r| Square::make_square(r, self.file()))
     }
 
     pub const fn const_up(&self) -> Option<Self> {
         match self.rank().const_up() {
-            Some(r) => {
-                return Some(Square::make_square(r, self.file()));
-            }
+            Some(r) => Some(Square::make_square(r, self.file())),
             None => None,
         }
     }
@@ -71,14 +69,12 @@ impl Square {
     pub fn down(&self) -> Option<Self> {
         self.rank()
             .down()
-            .and_then(|r| Some(Square::make_square(r, self.file())))
+            .map(|r| Square::make_square(r, self.file()))
     }
 
     pub const fn const_down(&self) -> Option<Self> {
         match self.rank().const_down() {
-            Some(r) => {
-                return Some(Square::make_square(r, self.file()));
-            }
+            Some(r) => Some(Square::make_square(r, self.file())),
             None => None,
         }
     }
@@ -87,14 +83,12 @@ impl Square {
     pub fn left(&self) -> Option<Self> {
         self.file()
             .left()
-            .and_then(|f| Some(Square::make_square(self.rank(), f)))
+            .map(|f| Square::make_square(self.rank(), f))
     }
 
     pub const fn const_left(&self) -> Option<Self> {
         match self.file().const_left() {
-            Some(f) => {
-                return Some(Square::make_square(self.rank(), f));
-            }
+            Some(f) => Some(Square::make_square(self.rank(), f)),
             None => None,
         }
     }
@@ -103,14 +97,12 @@ impl Square {
     pub fn right(&self) -> Option<Self> {
         self.file()
             .right()
-            .and_then(|f| Some(Square::make_square(self.rank(), f)))
+            .map(|f| Square::make_square(self.rank(), f))
     }
 
     pub const fn const_right(&self) -> Option<Self> {
         match self.file().const_right() {
-            Some(f) => {
-                return Some(Square::make_square(self.rank(), f));
-            }
+            Some(f) => Some(Square::make_square(self.rank(), f)),
             None => None,
         }
     }
@@ -232,8 +224,8 @@ impl fmt::Display for Square {
         write!(
             f,
             "{}{}",
-            (('a' as u8) + ((self.0 & 7) as u8)) as char,
-            (('1' as u8) + ((self.0 >> 3) as u8)) as char
+            (b'a' + (self.0 & 7)) as char,
+            (b'1' + (self.0 >> 3)) as char
         )
     }
 }
@@ -0,0 +1,515 @@
+use crate::bitboard::{BitBoard, EMPTY};
+use crate::board::Board;
+use crate::color::{Color, ALL_COLORS, NUM_COLORS};
+use crate::movegen::MoveGen;
+use crate::square::{Square, ALL_SQUARES, NUM_SQUARES};
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Write;
+use std::path::Path;
+
+const MAGIC: &[u8; 8] = b"CNTB0001";
+const HEADER_SIZE: usize = MAGIC.len() + 4 + 8;
+/// Squares a soldier can stand on, every square but the two towns
+const SLOTS: usize = NUM_SQUARES - 2;
+/// Stored value of draws, and of positions not decided yet while solving
+const UNKNOWN: u16 = 0;
+
+#[derive(Debug)]
+pub enum TablebaseError {
+    Io(std::io::Error),
+    InvalidFile(String),
+}
+
+impl fmt::Display for TablebaseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TablebaseError::Io(e) => write!(f, "{}", e),
+            TablebaseError::InvalidFile(s) => write!(f, "invalid tablebase file: {}", s),
+        }
+    }
+}
+
+impl std::error::Error for TablebaseError {}
+
+impl From<std::io::Error> for TablebaseError {
+    fn from(e: std::io::Error) -> Self {
+        TablebaseError::Io(e)
+    }
+}
+
+/// Game theoretic value for the side to move, with the number of plies until
+/// the town falls or the losing side has no move left.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TbResult {
+    Win(u16),
+    Loss(u16),
+    Draw,
+}
+
+impl TbResult {
+    fn encode(self) -> u16 {
+        match self {
+            TbResult::Draw => UNKNOWN,
+            TbResult::Win(d) => 1 + 2 * d,
+            TbResult::Loss(d) => 2 + 2 * d,
+        }
+    }
+
+    fn decode(value: u16) -> Self {
+        match value {
+            UNKNOWN => TbResult::Draw,
+            v if v % 2 == 1 => TbResult::Win((v - 1) / 2),
+            v => TbResult::Loss((v - 2) / 2),
+        }
+    }
+
+    /// Search score of the result, `win` is the score of taking the town right away.
+    /// Faster wins and slower losses score better.
+    pub fn score(self, win: i16) -> i16 {
+        match self {
+            TbResult::Win(d) => win - (d as i16).min(win / 2),
+            TbResult::Loss(d) => -(win - (d as i16).min(win / 2)),
+            TbResult::Draw => 0,
+        }
+    }
+}
+
+/// Identifies one table: the soldier counts of both colors and the town squares.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TableKey {
+    pub white: u8,
+    pub black: u8,
+    pub white_town: Square,
+    pub black_town: Square,
+}
+
+impl TableKey {
+    fn towns(&self) -> [BitBoard; NUM_COLORS] {
+        [
+            BitBoard::from_square(self.white_town),
+            BitBoard::from_square(self.black_town),
+        ]
+    }
+
+    /// File name like `w2b1_h1a8.tb`.
+    pub fn file_name(&self) -> String {
+        format!(
+            "w{}b{}_{}{}.tb",
+            self.white, self.black, self.white_town, self.black_town
+        )
+    }
+}
+
+fn binomial(n: usize, k: usize) -> usize {
+    if k > n {
+        return 0;
+    }
+    (0..k).fold(1, |acc, i| acc * (n - i) / (i + 1))
+}
+
+/// Maps positions of one table to indices. Soldiers of each color are ranked as a
+/// combination of the squares without towns, overlapping soldiers give unused indices.
+struct Layout {
+    key: TableKey,
+    slot_of: [u8; NUM_SQUARES],
+    squares: [Square; SLOTS],
+    white_size: usize,
+    black_size: usize,
+}
+
+impl Layout {
+    fn new(key: TableKey) -> Self {
+        let mut slot_of = [u8::MAX; NUM_SQUARES];
+        let mut squares = [Square::A1; SLOTS];
+        let mut slot = 0;
+        for sq in ALL_SQUARES {
+            if sq != key.white_town && sq != key.black_town {
+                slot_of[sq.to_index()] = slot as u8;
+                squares[slot] = sq;
+                slot += 1;
+            }
+        }
+        Self {
+            key,
+            slot_of,
+            squares,
+            white_size: binomial(SLOTS, key.white as usize),
+            black_size: binomial(SLOTS, key.black as usize),
+        }
+    }
+
+    fn size(&self) -> usize {
+        self.white_size * self.black_size * NUM_COLORS
+    }
+
+    /// Soldiers come out of the bitboard by ascending square, so their slots are sorted.
+    fn rank(&self, soldiers: BitBoard) -> usize {
+        soldiers
+            .enumerate()
+            .map(|(i, sq)| binomial(self.slot_of[sq.to_index()] as usize, i + 1))
+            .sum()
+    }
+
+    fn unrank(&self, mut rank: usize, count: usize) -> BitBoard {
+        let mut soldiers = EMPTY;
+        let mut slot = SLOTS;
+        for k in (1..=count).rev() {
+            slot -= 1;
+            while binomial(slot, k) > rank {
+                slot -= 1;
+            }
+            rank -= binomial(slot, k);
+            soldiers |= BitBoard::from_square(self.squares[slot]);
+        }
+        soldiers
+    }
+
+    fn index(&self, board: &Board) -> usize {
+        let white = self.rank(board.pieces_with_color(Color::White));
+        let black = self.rank(board.pieces_with_color(Color::Black));
+        (white * self.black_size + black) * NUM_COLORS + board.side_to_move().to_index()
+    }
+
+    /// The position at `index`, or None if soldiers overlap.
+    fn board(&self, index: usize) -> Option<Board> {
+        let side = ALL_COLORS[index % NUM_COLORS];
+        let index = index / NUM_COLORS;
+        let white = self.unrank(index / self.black_size, self.key.white as usize);
+        let black = self.unrank(index % self.black_size, self.key.black as usize);
+        if (white & black).is_not_empty() {
+            return None;
+        }
+        Some(Board::from_bitboards(
+            side,
+            [white, black],
+            self.key.towns(),
+        ))
+    }
+}
+
+/// Solved positions with few soldiers, generated by retrograde analysis.
+#[derive(Default)]
+pub struct Tablebase {
+    /// Values of every table with the layout that indexes them
+    tables: HashMap<TableKey, (Layout, Vec<u16>)>,
+}
+
+impl Tablebase {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn tables(&self) -> impl Iterator<Item = &TableKey> {
+        self.tables.keys()
+    }
+
+    fn insert(&mut self, key: TableKey, table: Vec<u16>) {
+        self.tables.insert(key, (Layout::new(key), table));
+    }
+
+    /// Every pair of towns on the home ranks.
+    pub fn all_towns() -> Vec<(Square, Square)> {
+        let white: Vec<Square> = ALL_SQUARES[..8].to_vec();
+        let black: Vec<Square> = ALL_SQUARES[56..].to_vec();
+        white
+            .iter()
+            .flat_map(|&w| black.iter().map(move |&b| (w, b)))
+            .collect()
+    }
+
+    /// Solves every position with up to `max_soldiers` soldiers per color for the town pairs,
+    /// tables with fewer soldiers first since captures lead into them.
+    /// `report` is called after every solved table.
+    pub fn generate<F: FnMut(&TableKey)>(
+        max_soldiers: u8,
+        towns: &[(Square, Square)],
+        mut report: F,
+    ) -> Self {
+        let mut tablebase = Self::new();
+        for &(white_town, black_town) in towns {
+            for total in 0..=2 * max_soldiers {
+                for white in total.saturating_sub(max_soldiers)..=total.min(max_soldiers) {
+                    let key = TableKey {
+                        white,
+                        black: total - white,
+                        white_town,
+                        black_town,
+                    };
+                    let table = tablebase.solve(key);
+                    tablebase.insert(key, table);
+                    report(&key);
+                }
+            }
+        }
+        tablebase
+    }
+
+    /// Looks up a position with both towns placed, None if no table covers it.
+    pub fn probe(&self, board: &Board) -> Option<TbResult> {
+        let white_town = board.castle_with_color(Color::White);
+        let black_town = board.castle_with_color(Color::Black);
+        if white_town.is_empty() || black_town.is_empty() {
+            return None;
+        }
+        let key = TableKey {
+            white: board.pieces_with_color(Color::White).count_bits() as u8,
+            black: board.pieces_with_color(Color::Black).count_bits() as u8,
+            white_town: white_town.to_square(),
+            black_town: black_town.to_square(),
+        };
+        let (layout, table) = self.tables.get(&key)?;
+        Some(TbResult::decode(table[layout.index(board)]))
+    }
+
+    /// Value of the position after a capture, which is in an already solved table.
+    fn probe_smaller(&self, board: &Board) -> TbResult {
+        self.probe(board).expect("smaller table is solved first")
+    }
+
+    fn solve(&self, key: TableKey) -> Vec<u16> {
+        let layout = Layout::new(key);
+        let size = layout.size();
+
+        // every position is decided by its moves out of the table, or waits for the
+        // given number of moves that stay in the table
+        let init: Vec<(u16, u8, u16)> = (0..size)
+            .into_par_iter()
+            .map(|index| match layout.board(index) {
+                Some(board) => self.init_position(&board),
+                None => (UNKNOWN, 0, 0),
+            })
+            .collect();
+        let mut values: Vec<u16> = init.iter().map(|e| e.0).collect();
+        let mut counters: Vec<u8> = init.iter().map(|e| e.1).collect();
+        let mut longest: Vec<u16> = init.iter().map(|e| e.2).collect();
+        drop(init);
+
+        let mut buckets: Vec<Vec<u32>> = Vec::new();
+        let push = |buckets: &mut Vec<Vec<u32>>, distance: u16, index: usize| {
+            let distance = distance as usize;
+            if buckets.len() <= distance {
+                buckets.resize(distance + 1, Vec::new());
+            }
+            buckets[distance].push(index as u32);
+        };
+        for (index, &value) in values.iter().enumerate() {
+            match TbResult::decode(value) {
+                TbResult::Win(d) | TbResult::Loss(d) => push(&mut buckets, d, index),
+                TbResult::Draw => {}
+            }
+        }
+
+        // positions are final once their distance is reached, wins only get shorter before
+        let mut done = vec![false; size];
+        let mut distance = 0;
+        while distance < buckets.len() {
+            let current: Vec<u32> = std::mem::take(&mut buckets[distance])
+                .into_iter()
+                .filter(|&index| {
+                    let index = index as usize;
+                    let fresh = !done[index]
+                        && matches!(TbResult::decode(values[index]),
+                            TbResult::Win(d) | TbResult::Loss(d) if d as usize == distance);
+                    done[index] = done[index] || fresh;
+                    fresh
+                })
+                .collect();
+            let predecessors: Vec<(u32, Vec<u32>)> = current
+                .par_iter()
+                .map(|&index| {
+                    let board = layout.board(index as usize).unwrap();
+                    let before = MoveGen::generate_reverse_moves(&board)
+                        .iter()
+                        .map(|(_, before)| layout.index(before) as u32)
+                        .collect();
+                    (index, before)
+                })
+                .collect();
+
+            let d = distance as u16;
+            for (index, before) in predecessors {
+                let lost = matches!(TbResult::decode(values[index as usize]), TbResult::Loss(_));
+                for pred in before {
+                    let pred = pred as usize;
+                    if done[pred] {
+                        continue;
+                    }
+                    let pred_value = TbResult::decode(values[pred]);
+                    if lost {
+                        let better = match pred_value {
+                            TbResult::Win(w) => d + 1 < w,
+                            _ => true,
+                        };
+                        if better {
+                            values[pred] = TbResult::Win(d + 1).encode();
+                            push(&mut buckets, d + 1, pred);
+                        }
+                    } else if !matches!(pred_value, TbResult::Win(_)) {
+                        debug_assert!(counters[pred] > 0);
+                        counters[pred] -= 1;
+                        longest[pred] = longest[pred].max(d);
+                        if counters[pred] == 0 {
+                            values[pred] = TbResult::Loss(longest[pred] + 1).encode();
+                            push(&mut buckets, longest[pred] + 1, pred);
+                        }
+                    }
+                }
+            }
+            distance += 1;
+        }
+        values
+    }
+
+    /// The value from moves leaving the table, the number of moves staying in it
+    /// and the longest loss among the moves leaving it.
+    fn init_position(&self, board: &Board) -> (u16, u8, u16) {
+        let moves = board.generate_moves();
+        if moves.is_empty() {
+            return (TbResult::Loss(0).encode(), 0, 0);
+        }
+        let mut win: Option<u16> = None;
+        let mut remaining = 0u8;
+        let mut longest = 0;
+        let mut board = board.shallow_clone();
+        for m in moves {
+            if board.captures_town(m) {
+                win = Some(1);
+                continue;
+            }
+            if !m.is_capture() {
+                remaining += 1;
+                continue;
+            }
+            board.apply_move(m);
+            match self.probe_smaller(&board) {
+                TbResult::Loss(d) => win = Some(win.map_or(d + 1, |w| w.min(d + 1))),
+                TbResult::Win(d) => longest = longest.max(d),
+                // a drawn capture keeps the position from ever being lost
+                TbResult::Draw => remaining = remaining.saturating_add(1),
+            }
+            board.undo_move();
+        }
+        match win {
+            Some(d) => (TbResult::Win(d).encode(), remaining, longest),
+            None if remaining == 0 => (TbResult::Loss(longest + 1).encode(), 0, longest),
+            None => (UNKNOWN, remaining, longest),
+        }
+    }
+
+    /// Writes every table into its own file in `dir`.
+    pub fn write_dir<P: AsRef<Path>>(&self, dir: P) -> Result<(), TablebaseError> {
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir)?;
+        for (key, (_, table)) in self.tables.iter() {
+            let mut bytes = Vec::with_capacity(HEADER_SIZE + table.len() * 2);
+            bytes.extend(MAGIC);
+            bytes.extend([
+                key.white,
+                key.black,
+                key.white_town.to_index() as u8,
+                key.black_town.to_index() as u8,
+            ]);
+            bytes.extend((table.len() as u64).to_le_bytes());
+            for value in table.iter() {
+                bytes.extend(value.to_le_bytes());
+            }
+            std::fs::File::create(dir.join(key.file_name()))?.write_all(&bytes)?;
+        }
+        Ok(())
+    }
+
+    /// Loads every `.tb` file in `dir`.
+    pub fn load_dir<P: AsRef<Path>>(dir: P) -> Result<Self, TablebaseError> {
+        let mut tablebase = Self::new();
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|e| e == "tb") {
+                let (key, table) = Self::read_table(&std::fs::read(&path)?)?;
+                tablebase.insert(key, table);
+            }
+        }
+        Ok(tablebase)
+    }
+
+    fn read_table(bytes: &[u8]) -> Result<(TableKey, Vec<u16>), TablebaseError> {
+        let invalid = |s: &str| TablebaseError::InvalidFile(s.to_string());
+        if bytes.len() < HEADER_SIZE || &bytes[..MAGIC.len()] != MAGIC {
+            return Err(invalid("wrong magic"));
+        }
+        let header = &bytes[MAGIC.len()..];
+        if header[2] as usize >= NUM_SQUARES || header[3] as usize >= NUM_SQUARES {
+            return Err(invalid("town off the board"));
+        }
+        let key = TableKey {
+            white: header[0],
+            black: header[1],
+            white_town: Square::new(header[2]),
+            black_town: Square::new(header[3]),
+        };
+        let entries = u64::from_le_bytes(header[4..12].try_into().unwrap()) as usize;
+        if entries != Layout::new(key).size() || bytes.len() != HEADER_SIZE + entries * 2 {
+            return Err(invalid("wrong table size"));
+        }
+        let table = bytes[HEADER_SIZE..]
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .collect();
+        Ok((key, table))
+    }
+}
+
+#[test]
+fn tablebase_test() {
+    use crate::search::Searcher;
+
+    let towns = [(Square::H1, Square::A8)];
+    let tablebase = Tablebase::generate(1, &towns, |_| {});
+    assert_eq!(tablebase.tables().count(), 4);
+
+    let layout = Layout::new(TableKey {
+        white: 1,
+        black: 1,
+        white_town: Square::H1,
+        black_town: Square::A8,
+    });
+    for index in [0, 1, 17, layout.size() - 1] {
+        if let Some(board) = layout.board(index) {
+            assert_eq!(layout.index(&board), index);
+        }
+    }
+
+    // no soldiers left to move
+    let board = Board::from_fen("B7/8/8/8/8/8/8/7W w").unwrap();
+    assert_eq!(tablebase.probe(&board), Some(TbResult::Loss(0)));
+    let board = Board::from_fen("B7/w7/8/8/8/8/8/7W w").unwrap();
+    assert_eq!(tablebase.probe(&board), Some(TbResult::Win(1)));
+
+    // the tables agree with a plain search on positions that are decided within its depth
+    let mut searcher = Searcher::with_tt_size(1 << 12);
+    searcher.verbose = false;
+    for fen in [
+        "B7/8/8/w7/8/8/8/7W w",
+        "B7/8/8/w7/8/8/8/7W b",
+        "B7/8/1b6/8/8/8/6w1/7W w",
+        "B7/8/8/8/3b4/3w4/8/7W b",
+    ] {
+        let mut board = Board::from_fen(fen).unwrap();
+        let result = tablebase.probe(&board).unwrap();
+        let score = searcher.search(&mut board, 6).score();
+        match result {
+            TbResult::Win(d) if d < 6 => assert!(score > 4000, "{} {:?} {}", fen, result, score),
+            TbResult::Loss(d) if d < 6 => assert!(score < -4000, "{} {:?} {}", fen, result, score),
+            _ => {}
+        }
+    }
+
+    let dir = std::env::temp_dir().join(format!("tablebase_test_{}", std::process::id()));
+    tablebase.write_dir(&dir).unwrap();
+    let loaded = Tablebase::load_dir(&dir).unwrap();
+    assert_eq!(loaded.tables().count(), 4);
+    let board = Board::from_fen("B7/8/1b6/8/8/8/6w1/7W w").unwrap();
+    assert_eq!(loaded.probe(&board), tablebase.probe(&board));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -1,266 +1,309 @@
 use crate::bitboard::*;
 use crate::color::*;
 use crate::square::Square;
-use crate::square::ALL_SQUARES;
 
-// TODO make this safe (remove mut)
-static mut FRONT: [[BitBoard; 64]; 2] = [[EMPTY; 64]; 2];
-static mut SIDES: [[BitBoard; 64]; 2] = [[EMPTY; 64]; 2];
-static mut RETREATS: [[BitBoard; 64]; 2] = [[EMPTY; 64]; 2];
-static mut DISTANCES: [[u8; 64]; 64] = [[0; 64]; 64];
-static mut DISTANCE_RINGS: [[BitBoard; 64]; 8] = [[EMPTY; 64]; 8];
-static mut DIAGONALS: [BitBoard; 64] = [EMPTY; 64];
-static mut ORTHOGONALS: [BitBoard; 64] = [EMPTY; 64];
-static mut BETWEEN: [[BitBoard; 64]; 64] = [[EMPTY; 64]; 64];
-// TODO consider splitting this into 3 different arrays
-static mut SHOTS: [[[BitBoard; 64]; 8]; 3] = [[[EMPTY; 64]; 8]; 3];
+// All tables are evaluated at compile time, so no initialization is needed before use.
+static FRONT: [[BitBoard; 64]; 2] = generate_front();
+static SIDES: [[BitBoard; 64]; 2] = generate_sides();
+static RETREATS: [[BitBoard; 64]; 2] = generate_retreats();
+static DISTANCES: [[u8; 64]; 64] = generate_distances();
+static DISTANCE_RINGS: [[BitBoard; 64]; 8] = generate_distance_rings();
+static DIAGONALS: [BitBoard; 64] = generate_diagonals();
+static ORTHOGONALS: [BitBoard; 64] = generate_orthogonals();
+static BETWEEN: [[BitBoard; 64]; 64] = generate_between();
+static SHOT_BLOCKERS: ShotTable = generate_shots().0;
+static SHOT_BODIES: ShotTable = generate_shots().1;
+static SHOT_TARGETS: ShotTable = generate_shots().2;
+
+type ShotTable = [[BitBoard; 64]; 8];
+
+/// Unit steps as (file, rank) deltas for all 8 directions.
+const STEPS: [(i8, i8); 8] = [
+    (-1, -1),
+    (-1, 0),
+    (-1, 1),
+    (0, -1),
+    (0, 1),
+    (1, -1),
+    (1, 0),
+    (1, 1),
+];
+
+const fn square_bb(sq: Option<Square>) -> u64 {
+    match sq {
+        Some(sq) => 1u64 << sq.0,
+        None => 0,
+    }
+}
 
-pub fn generate_front() {
-    for c in ALL_COLORS.iter() {
-        for src in ALL_SQUARES.iter() {
-            let forward = src.forward(*c);
-            let forward_left = forward.map_or(EMPTY, |sq| {
-                sq.left().map_or(EMPTY, |sq| BitBoard::from_square(sq))
-            });
-            let forward_right = forward.map_or(EMPTY, |sq| {
-                sq.right().map_or(EMPTY, |sq| BitBoard::from_square(sq))
-            });
-            let forward = forward.map_or(EMPTY, |sq| BitBoard::from_square(sq));
+const fn offset(sq: Square, df: i8, dr: i8) -> Option<Square> {
+    let f = sq.file_index() as i8 + df;
+    let r = sq.rank_index() as i8 + dr;
+    if f < 0 || f > 7 || r < 0 || r > 7 {
+        None
+    } else {
+        Some(Square::new((r as u8) << 3 | f as u8))
+    }
+}
 
-            let combined = forward | forward_left | forward_right;
+/// Index of the direction `x`, `y` where each component is the sign of `src - dst`.
+const fn direction_to_index(x: i8, y: i8) -> usize {
+    match (x, y) {
+        (-1, -1) => 0,
+        (-1, 0) => 1,
+        (-1, 1) => 2,
+        (0, -1) => 3,
+        (0, 1) => 4,
+        (1, -1) => 5,
+        (1, 0) => 6,
+        (1, 1) => 7,
+        _ => panic!("Should never happen"),
+    }
+}
 
-            unsafe {
-                FRONT[c.to_index()][src.to_index()] = combined;
-            }
+pub const fn generate_front() -> [[BitBoard; 64]; 2] {
+    let mut res = [[EMPTY; 64]; 2];
+    let mut c = 0;
+    while c < 2 {
+        let color = ALL_COLORS[c];
+        let mut i = 0;
+        while i < 64 {
+            let forward = Square::new(i as u8).const_forward(color);
+            let (forward_left, forward_right) = match forward {
+                Some(sq) => (sq.const_left(), sq.const_right()),
+                None => (None, None),
+            };
+            res[c][i] = BitBoard::new(
+                square_bb(forward) | square_bb(forward_left) | square_bb(forward_right),
+            );
+            i += 1;
         }
+        c += 1;
     }
+    res
 }
 
-pub fn generate_sides() {
-    for c in ALL_COLORS.iter() {
-        for src in ALL_SQUARES.iter() {
-            let left = src.left().map_or(EMPTY, |sq| BitBoard::from_square(sq));
-            let right = src.right().map_or(EMPTY, |sq| BitBoard::from_square(sq));
-            let combined = left | right;
-
-            unsafe {
-                SIDES[c.to_index()][src.to_index()] = combined;
-            }
+pub const fn generate_sides() -> [[BitBoard; 64]; 2] {
+    let mut res = [[EMPTY; 64]; 2];
+    let mut c = 0;
+    while c < 2 {
+        let mut i = 0;
+        while i < 64 {
+            let src = Square::new(i as u8);
+            res[c][i] = BitBoard::new(square_bb(src.const_left()) | square_bb(src.const_right()));
+            i += 1;
         }
+        c += 1;
     }
+    res
 }
 
-pub fn generate_retreats() {
-    for c in ALL_COLORS.iter() {
-        for src in ALL_SQUARES.iter() {
-            let two_down = src.backward(*c).and_then(|sq| sq.backward(*c));
-
-            let left = two_down
-                .and_then(|sq| sq.left().and_then(|sq| sq.left()))
-                .map_or(EMPTY, |sq| BitBoard::from_square(sq));
-            let right = two_down
-                .and_then(|sq| sq.right().and_then(|sq| sq.right()))
-                .map_or(EMPTY, |sq| BitBoard::from_square(sq));
-            let two_down = two_down.map_or(EMPTY, |sq| BitBoard::from_square(sq));
-            let combined = two_down | left | right;
-            unsafe {
-                RETREATS[c.to_index()][src.to_index()] = combined;
-            }
+pub const fn generate_retreats() -> [[BitBoard; 64]; 2] {
+    let mut res = [[EMPTY; 64]; 2];
+    let mut c = 0;
+    while c < 2 {
+        let color = ALL_COLORS[c];
+        let mut i = 0;
+        while i < 64 {
+            let two_down = match Square::new(i as u8).const_backward(color) {
+                Some(sq) => sq.const_backward(color),
+                None => None,
+            };
+            let (left, right) = match two_down {
+                Some(sq) => (offset(sq, -2, 0), offset(sq, 2, 0)),
+                None => (None, None),
+            };
+            res[c][i] = BitBoard::new(square_bb(two_down) | square_bb(left) | square_bb(right));
+            i += 1;
         }
+        c += 1;
     }
+    res
 }
 
-pub fn generate_distances() {
-    for i in 0..64 as u8 {
-        for j in 0..64 as u8 {
-            unsafe {
-                let sqi = Square::new(i);
-                let sqj = Square::new(j);
-                DISTANCES[i as usize][j as usize] = (sqi.rank_index().abs_diff(sqj.rank_index()))
-                    .max(sqi.file_index().abs_diff(sqj.file_index()));
-            }
+pub const fn generate_distances() -> [[u8; 64]; 64] {
+    let mut res = [[0; 64]; 64];
+    let mut i = 0;
+    while i < 64 {
+        let mut j = 0;
+        while j < 64 {
+            let sqi = Square::new(i as u8);
+            let sqj = Square::new(j as u8);
+            let rank_diff = sqi.rank_index().abs_diff(sqj.rank_index());
+            let file_diff = sqi.file_index().abs_diff(sqj.file_index());
+            res[i][j] = if rank_diff > file_diff {
+                rank_diff
+            } else {
+                file_diff
+            };
+            j += 1;
         }
+        i += 1;
     }
+    res
 }
 
-pub fn generate_distance_rings() {
-    for i in 0..64 {
-        for j in 0..64 {
+pub const fn generate_distance_rings() -> [[BitBoard; 64]; 8] {
+    let distances = generate_distances();
+    let mut res = [[EMPTY; 64]; 8];
+    let mut i = 0;
+    while i < 64 {
+        let mut j = 0;
+        while j < 64 {
             if i != j {
-                let dist = distance_index(i, j) as usize;
-                unsafe { DISTANCE_RINGS[dist - 1][i] |= (1 as u64) << (j as usize) }
+                let dist = distances[i][j] as usize;
+                res[dist - 1][i] = BitBoard::new(res[dist - 1][i].0 | 1u64 << j);
             }
+            j += 1;
         }
+        i += 1;
     }
+    res
 }
 
-pub fn generate_diagonals() {
-    for i in 0..64 {
-        let src = Square::new(i);
-        let res = ALL_SQUARES
-            .iter()
-            .filter(|dst| {
-                src.rank_index().abs_diff(dst.rank_index())
-                    == (src.file_index().abs_diff(dst.file_index()))
-                    && src != **dst
-            })
-            .fold(EMPTY, |b, s| b | BitBoard::from_square(*s));
-        unsafe { DIAGONALS[i as usize] = res }
+pub const fn generate_diagonals() -> [BitBoard; 64] {
+    let mut res = [EMPTY; 64];
+    let mut i = 0;
+    while i < 64 {
+        let src = Square::new(i as u8);
+        let mut j = 0;
+        while j < 64 {
+            let dst = Square::new(j as u8);
+            if i != j
+                && src.rank_index().abs_diff(dst.rank_index())
+                    == src.file_index().abs_diff(dst.file_index())
+            {
+                res[i] = BitBoard::new(res[i].0 | 1u64 << j);
+            }
+            j += 1;
+        }
+        i += 1;
     }
+    res
 }
 
-pub fn generate_orthogonals() {
-    for i in 0..64 {
-        let src = Square::new(i);
-        let res = ALL_SQUARES
-            .iter()
-            .filter(|dst| {
-                (src.rank_index() == dst.rank_index() || src.file_index() == dst.file_index())
-                    && src != **dst
-            })
-            .fold(EMPTY, |b, s| b | BitBoard::from_square(*s));
-        unsafe { ORTHOGONALS[i as usize] = res }
+pub const fn generate_orthogonals() -> [BitBoard; 64] {
+    let mut res = [EMPTY; 64];
+    let mut i = 0;
+    while i < 64 {
+        let src = Square::new(i as u8);
+        let mut j = 0;
+        while j < 64 {
+            let dst = Square::new(j as u8);
+            if i != j
+                && (src.rank_index() == dst.rank_index() || src.file_index() == dst.file_index())
+            {
+                res[i] = BitBoard::new(res[i].0 | 1u64 << j);
+            }
+            j += 1;
+        }
+        i += 1;
     }
+    res
 }
 
-pub fn generate_between() {
-    let between = |a: u8, t: u8, b: u8| -> bool {
-        if a < b {
-            a < t && t < b
-        } else {
-            b < t && t < a
+/// Squares strictly between two squares on a common line, or empty if they are not aligned.
+pub const fn generate_between() -> [[BitBoard; 64]; 64] {
+    let lines = {
+        let diagonals = generate_diagonals();
+        let orthogonals = generate_orthogonals();
+        let mut lines = [0u64; 64];
+        let mut i = 0;
+        while i < 64 {
+            lines[i] = diagonals[i].0 | orthogonals[i].0;
+            i += 1;
         }
+        lines
     };
-
-    let between_square = |sqa: Square, sqt: Square, sqb: Square| -> bool {
-        between(sqa.file_index(), sqt.file_index(), sqb.file_index())
-            || between(sqa.rank_index(), sqt.rank_index(), sqb.rank_index())
-    };
-    for (i, src) in ALL_SQUARES.iter().enumerate() {
-        for (j, dst) in ALL_SQUARES.iter().enumerate() {
-            let dst_bb = BitBoard::from_square(*dst);
-
-            if (orthogonals(*src) & dst_bb).is_not_empty() {
-                let bb = orthogonals(*src) & orthogonals(*dst);
-                let res = bb
-                    .into_iter()
-                    .filter(|sq| between_square(*src, *sq, *dst))
-                    .fold(EMPTY, |b, s| b | BitBoard::from_square(s));
-                unsafe { BETWEEN[i as usize][j as usize] = res }
-            } else if (diagonals(*src) & dst_bb).is_not_empty() {
-                let bb = diagonals(*src) & diagonals(*dst);
-                let res = bb
-                    .into_iter()
-                    .filter(|sq| between_square(*src, *sq, *dst))
-                    .fold(EMPTY, |b, s| b | BitBoard::from_square(s));
-                unsafe { BETWEEN[i as usize][j as usize] = res }
-            } else {
-                unsafe { BETWEEN[i as usize][j as usize] = EMPTY }
+    let mut res = [[EMPTY; 64]; 64];
+    let mut i = 0;
+    while i < 64 {
+        let src = Square::new(i as u8);
+        let mut j = 0;
+        while j < 64 {
+            if lines[i] & (1u64 << j) != 0 {
+                let dst = Square::new(j as u8);
+                let df = (dst.file_index() as i8 - src.file_index() as i8).signum();
+                let dr = (dst.rank_index() as i8 - src.rank_index() as i8).signum();
+                let mut bb = 0;
+                let mut sq = offset(src, df, dr);
+                while let Some(s) = sq {
+                    if s.0 == dst.0 {
+                        break;
+                    }
+                    bb |= 1u64 << s.0;
+                    sq = offset(s, df, dr);
+                }
+                res[i][j] = BitBoard::new(bb);
             }
+            j += 1;
         }
+        i += 1;
     }
+    res
 }
 
-pub fn generate_shots() {
-    let direction_to_index = |src: Square, dst: Square| -> usize {
-        let x = src.file_index() as i8 - dst.file_index() as i8;
-        let y = src.rank_index() as i8 - dst.rank_index() as i8;
-        let x = x.clamp(-1, 1);
-        let y = y.clamp(-1, 1);
-        match x {
-            -1 => match y {
-                -1 => 0,
-                0 => 1,
-                1 => 2,
-                _ => panic!("Should never happen -1 OOR"),
-            },
-            0 => match y {
-                -1 => 3,
-                0 => panic!("Should never happen 0, 0"),
-                1 => 4,
-                _ => panic!("Should never happen 0 OOR"),
-            },
-            1 => match y {
-                -1 => 5,
-                0 => 6,
-                1 => 7,
-                _ => panic!("Should never happen 1 OOR"),
-            },
-            _ => panic!("Should never happen"),
-        }
-    };
-
-    for (idx, sq) in ALL_SQUARES.iter().enumerate() {
-        let blocker = distance_dir_ring(*sq, 1);
-        for b in blocker {
-            let dir = direction_to_index(*sq, b);
-            unsafe { SHOTS[0][dir][idx] = BitBoard::from_square(b) }
-        }
-
-        let cannon_tail = distance_dir_ring(*sq, 2);
-        for tail in cannon_tail {
-            let between = between(*sq, tail);
-            // find the index of the opposite direction
-            let dir = direction_to_index(between.to_square(), *sq);
-            let combined = BitBoard::from_square(tail) | between;
-            unsafe { SHOTS[1][dir][idx] = combined }
-        }
-
-        let dist_2_shots = distance_dir_ring(*sq, 2);
-        for shot in dist_2_shots {
-            let dir = direction_to_index(*sq, shot);
-            unsafe { SHOTS[2][dir][idx] = BitBoard::from_square(shot) }
-        }
-
-        let dist_3_shots = distance_dir_ring(*sq, 3);
-        for shot in dist_3_shots {
-            let dir = direction_to_index(*sq, shot);
-            unsafe { SHOTS[2][dir][idx] |= BitBoard::from_square(shot) }
+/// Returns the shot blockers, cannon bodies and shot targets for every direction and square.
+///
+/// For direction `d` the blocker is the adjacent square in front of the cannon head,
+/// the body are the two squares behind it and the targets are 2 and 3 squares in front.
+pub const fn generate_shots() -> (ShotTable, ShotTable, ShotTable) {
+    let mut blockers = [[EMPTY; 64]; 8];
+    let mut bodies = [[EMPTY; 64]; 8];
+    let mut targets = [[EMPTY; 64]; 8];
+    let mut i = 0;
+    while i < 64 {
+        let sq = Square::new(i as u8);
+        let mut s = 0;
+        while s < 8 {
+            let (df, dr) = STEPS[s];
+            let dir = direction_to_index(-df, -dr);
+            let one = offset(sq, df, dr);
+            let two = offset(sq, 2 * df, 2 * dr);
+            let three = offset(sq, 3 * df, 3 * dr);
+            blockers[dir][i] = BitBoard::new(square_bb(one));
+            targets[dir][i] = BitBoard::new(square_bb(two) | square_bb(three));
+            if two.is_some() {
+                // the body lies in the opposite direction of the shot
+                bodies[direction_to_index(df, dr)][i] =
+                    BitBoard::new(square_bb(one) | square_bb(two));
+            }
+            s += 1;
         }
+        i += 1;
     }
-}
-
-pub fn init() {
-    generate_front();
-    generate_sides();
-    generate_retreats();
-    generate_distances();
-    generate_distance_rings(); // depends on distances
-    generate_diagonals();
-    generate_orthogonals();
-    generate_between(); // depends on diagonals and orthogonals
-    generate_shots();
+    (blockers, bodies, targets)
 }
 
 #[inline(always)]
 pub fn front(color: Color, square: Square) -> BitBoard {
-    unsafe { FRONT[color.to_index()][square.to_index()] }
+    FRONT[color.to_index()][square.to_index()]
 }
 
 #[inline(always)]
 pub fn sides(color: Color, square: Square) -> BitBoard {
-    unsafe { SIDES[color.to_index()][square.to_index()] }
+    SIDES[color.to_index()][square.to_index()]
 }
 
 #[inline(always)]
 pub fn retreats(color: Color, square: Square) -> BitBoard {
-    unsafe { RETREATS[color.to_index()][square.to_index()] }
+    RETREATS[color.to_index()][square.to_index()]
 }
 
 #[inline(always)]
 pub fn distance_index(from: usize, to: usize) -> u8 {
-    unsafe { DISTANCES[from][to] }
+    DISTANCES[from][to]
 }
 
 #[inline(always)]
 pub fn distance_square(from: Square, to: Square) -> u8 {
-    unsafe { DISTANCES[from.to_index()][to.to_index()] }
+    DISTANCES[from.to_index()][to.to_index()]
 }
 
 #[inline(always)]
 pub fn distance_ring(square: Square, dist: usize) -> BitBoard {
     assert!(dist > 0 && dist <= 7);
-    unsafe { DISTANCE_RINGS[dist - 1][square.to_index()] }
+    DISTANCE_RINGS[dist - 1][square.to_index()]
 }
 
 #[inline(always)]
@@ -270,46 +313,50 @@ pub fn distance_dir_ring(src: Square, dist: usize) -> BitBoard {
 
 #[inline(always)]
 pub fn diagonals(square: Square) -> BitBoard {
-    unsafe { DIAGONALS[square.to_index()] }
+    DIAGONALS[square.to_index()]
 }
 
 #[inline(always)]
 pub fn orthogonals(square: Square) -> BitBoard {
-    unsafe { ORTHOGONALS[square.to_index()] }
+    ORTHOGONALS[square.to_index()]
 }
 
 #[inline(always)]
 pub fn between(src: Square, dst: Square) -> BitBoard {
-    unsafe { BETWEEN[src.to_index()][dst.to_index()] }
+    BETWEEN[src.to_index()][dst.to_index()]
 }
 
 #[inline(always)]
 pub fn shot_blocker(src: Square, dir: usize) -> BitBoard {
-    unsafe { SHOTS[0][dir][src.to_index()] }
+    SHOT_BLOCKERS[dir][src.to_index()]
 }
 
 #[inline(always)]
 pub fn shot_body(src: Square, dir: usize) -> BitBoard {
-    unsafe { SHOTS[1][dir][src.to_index()] }
+    SHOT_BODIES[dir][src.to_index()]
 }
 
 #[inline(always)]
 pub fn shot_targets(src: Square, dir: usize) -> BitBoard {
-    unsafe { SHOTS[2][dir][src.to_index()] }
+    SHOT_TARGETS[dir][src.to_index()]
 }
 
 #[test]
 fn tables_test() {
-    init();
     let middle = Square::D4;
 
-    let mut all_shots = EMPTY;
     let middle_bb = BitBoard::from_square(middle);
     for i in 0..8 {
-        println!(
-            "{}",
-            shot_targets(middle, i) | shot_body(middle, i) | middle_bb
-        );
-        //all_shots |= shot_blocker(middle, i);
+        // the blocker is adjacent in the shot direction and the body sits on the opposite side
+        assert_eq!(shot_blocker(middle, i).count_bits(), 1);
+        assert_eq!(shot_body(middle, i).count_bits(), 2);
+        assert!((shot_targets(middle, i) & distance_ring(middle, 2)).is_not_empty());
+        assert!((between(shot_blocker(middle, i).to_square(), middle) & middle_bb).is_empty());
     }
+    assert_eq!(front(Color::White, Square::A1).count_bits(), 2);
+    assert_eq!(front(Color::Black, Square::D8).count_bits(), 3);
+    assert_eq!(retreats(Color::White, Square::D4).count_bits(), 3);
+    assert_eq!(between(Square::A1, Square::D4).count_bits(), 2);
+    assert_eq!(between(Square::A1, Square::B3), EMPTY);
+    assert_eq!(distance_square(Square::A1, Square::H8), 7);
 }
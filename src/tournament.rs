@@ -0,0 +1,376 @@
+use crate::board::Board;
+use crate::engine_match::{play_game, Score};
+use crate::player::{from_spec, Player};
+use crate::record::{append_records, read_records, GameRecord, RecordError};
+use crate::search::SearchLimits;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Schedule {
+    /// Every engine plays every other engine
+    RoundRobin,
+    /// The first engine plays every other engine
+    Gauntlet,
+}
+
+impl Schedule {
+    pub fn pairings(self, engines: usize) -> Vec<(usize, usize)> {
+        match self {
+            Schedule::RoundRobin => (0..engines)
+                .flat_map(|a| (a + 1..engines).map(move |b| (a, b)))
+                .collect(),
+            Schedule::Gauntlet => (1..engines).map(|b| (0, b)).collect(),
+        }
+    }
+}
+
+/// A game of the schedule, engines are indices into the tournament's engines.
+#[derive(Clone, Debug)]
+pub struct ScheduledGame {
+    pub white: usize,
+    pub black: usize,
+    pub opening: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct TournamentConfig {
+    pub schedule: Schedule,
+    /// Games of every pairing, every opening is played twice with swapped colors
+    pub games_per_pair: usize,
+    pub limits: SearchLimits,
+    pub max_plies: usize,
+    /// Games played at the same time
+    pub concurrency: usize,
+}
+
+impl Default for TournamentConfig {
+    fn default() -> Self {
+        Self {
+            schedule: Schedule::RoundRobin,
+            games_per_pair: 10,
+            limits: SearchLimits::depth(6),
+            max_plies: 300,
+            concurrency: 1,
+        }
+    }
+}
+
+/// Engines are given as player specs, see `player::from_spec`,
+/// which also name them in the game records.
+pub struct Tournament {
+    pub engines: Vec<String>,
+    pub openings: Vec<Board>,
+    pub config: TournamentConfig,
+}
+
+impl Tournament {
+    pub fn schedule(&self) -> Vec<ScheduledGame> {
+        let mut games = Vec::new();
+        for (a, b) in self.config.schedule.pairings(self.engines.len()) {
+            for i in 0..self.config.games_per_pair {
+                let (white, black) = if i % 2 == 0 { (a, b) } else { (b, a) };
+                games.push(ScheduledGame {
+                    white,
+                    black,
+                    opening: (i / 2) % self.openings.len(),
+                });
+            }
+        }
+        games
+    }
+
+    /// The scheduled games without a game record in `records`.
+    pub fn pending(&self, records: &[GameRecord]) -> Vec<ScheduledGame> {
+        let mut played: HashMap<(&str, &str, &str), usize> = HashMap::new();
+        for record in records {
+            if let Some((white, black)) = &record.players {
+                *played
+                    .entry((white.as_str(), black.as_str(), record.start.as_str()))
+                    .or_default() += 1;
+            }
+        }
+        let fens: Vec<String> = self.openings.iter().map(|b| b.to_fen()).collect();
+        self.schedule()
+            .into_iter()
+            .filter(|game| {
+                let key = (
+                    self.engines[game.white].as_str(),
+                    self.engines[game.black].as_str(),
+                    fens[game.opening].as_str(),
+                );
+                match played.get_mut(&key) {
+                    Some(count) if *count > 0 => {
+                        *count -= 1;
+                        false
+                    }
+                    _ => true,
+                }
+            })
+            .collect()
+    }
+
+    /// Plays all games that are not yet in the records at `path` and appends them there,
+    /// so an interrupted tournament continues where it stopped. `on_game` is called
+    /// after every new game with the crosstable so far.
+    pub fn run<P: AsRef<Path>, F: FnMut(&GameRecord, &Crosstable)>(
+        &self,
+        path: P,
+        mut on_game: F,
+    ) -> Result<Crosstable, RecordError> {
+        let path = path.as_ref();
+        let records = if path.exists() {
+            read_records(path)?
+        } else {
+            Vec::new()
+        };
+        let mut table = Crosstable::new(self.engines.clone());
+        for record in records.iter() {
+            table.add(record);
+        }
+        let pending = self.pending(&records);
+
+        let next = AtomicUsize::new(0);
+        let mut result = Ok(());
+        std::thread::scope(|scope| {
+            let (sender, receiver) = mpsc::channel();
+            for _ in 0..self.config.concurrency.max(1) {
+                let sender = sender.clone();
+                let (next, pending) = (&next, &pending);
+                scope.spawn(move || {
+                    // every thread creates the engines it needs once
+                    let mut players: HashMap<usize, Box<dyn Player>> = HashMap::new();
+                    loop {
+                        let index = next.fetch_add(1, Ordering::SeqCst);
+                        let Some(game) = pending.get(index) else {
+                            break;
+                        };
+                        for engine in [game.white, game.black] {
+                            players.entry(engine).or_insert_with(|| {
+                                from_spec(&self.engines[engine]).unwrap_or_else(|e| {
+                                    panic!("engine {}: {}", self.engines[engine], e)
+                                })
+                            });
+                        }
+                        let mut white = players.remove(&game.white).unwrap();
+                        let mut black = players.remove(&game.black).unwrap();
+                        white.new_game();
+                        black.new_game();
+                        let record = play_game(
+                            &mut *white,
+                            &mut *black,
+                            &self.openings[game.opening],
+                            &self.config.limits,
+                            self.config.max_plies,
+                        )
+                        .with_players(&self.engines[game.white], &self.engines[game.black]);
+                        players.insert(game.white, white);
+                        players.insert(game.black, black);
+                        if sender.send(record).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+            drop(sender);
+
+            for record in receiver {
+                if result.is_ok() {
+                    result = append_records(path, std::slice::from_ref(&record));
+                }
+                table.add(&record);
+                on_game(&record, &table);
+            }
+        });
+        result.map(|_| table)
+    }
+}
+
+/// Results between every pair of engines.
+#[derive(Clone, Debug)]
+pub struct Crosstable {
+    pub engines: Vec<String>,
+    /// `scores[a][b]` is the score of `a` against `b`
+    pub scores: Vec<Vec<Score>>,
+}
+
+impl Crosstable {
+    pub fn new(engines: Vec<String>) -> Self {
+        let n = engines.len();
+        Self {
+            engines,
+            scores: vec![vec![Score::default(); n]; n],
+        }
+    }
+
+    /// Records of engines that are not part of the tournament are ignored.
+    pub fn add(&mut self, record: &GameRecord) {
+        let Some((white, black)) = &record.players else {
+            return;
+        };
+        let index = |name: &str| self.engines.iter().position(|e| e == name);
+        if let (Some(w), Some(b)) = (index(white), index(black)) {
+            self.scores[w][b].add(record.result, crate::color::Color::White);
+            self.scores[b][w].add(record.result, crate::color::Color::Black);
+        }
+    }
+
+    pub fn total(&self, engine: usize) -> Score {
+        self.scores[engine]
+            .iter()
+            .fold(Score::default(), |acc, s| Score {
+                wins: acc.wins + s.wins,
+                draws: acc.draws + s.draws,
+                losses: acc.losses + s.losses,
+            })
+    }
+
+    /// Bradley-Terry strengths fitted with minorization-maximization and converted to Elo,
+    /// with the average engine at 0. Draws count as half a win for both engines and every
+    /// pairing gets one virtual draw so engines without a loss still get a finite rating.
+    pub fn ratings(&self) -> Vec<f64> {
+        let n = self.engines.len();
+        let games = |a: usize, b: usize| {
+            let s = self.scores[a][b];
+            (s.wins + s.draws + s.losses) as f64
+        };
+        let played = |a: usize, b: usize| games(a, b) > 0.0;
+        let points = |a: usize, b: usize| {
+            let s = self.scores[a][b];
+            s.wins as f64 + s.draws as f64 / 2.0
+        };
+
+        let mut strength = vec![1.0; n];
+        for _ in 0..1000 {
+            let mut next = strength.clone();
+            for a in 0..n {
+                let mut wins = 0.0;
+                let mut denominator = 0.0;
+                for b in (0..n).filter(|&b| b != a && played(a, b)) {
+                    wins += points(a, b) + 0.5;
+                    denominator += (games(a, b) + 1.0) / (strength[a] + strength[b]);
+                }
+                if denominator > 0.0 {
+                    next[a] = wins / denominator;
+                }
+            }
+            // keep the geometric mean at 1
+            let mean = next.iter().map(|s: &f64| s.ln()).sum::<f64>() / n.max(1) as f64;
+            for s in next.iter_mut() {
+                *s /= mean.exp();
+            }
+            let change = next
+                .iter()
+                .zip(strength.iter())
+                .map(|(a, b)| (a - b).abs())
+                .fold(0.0, f64::max);
+            strength = next;
+            if change < 1e-9 {
+                break;
+            }
+        }
+        strength.iter().map(|s| 400.0 * s.log10()).collect()
+    }
+}
+
+impl fmt::Display for Crosstable {
+    /// One row per engine sorted by rating, with the points against every opponent.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ratings = self.ratings();
+        let mut order: Vec<usize> = (0..self.engines.len()).collect();
+        order.sort_by(|a, b| ratings[*b].total_cmp(&ratings[*a]));
+
+        write!(f, "{:<4}{:<24}{:>8}{:>10}", "#", "engine", "elo", "points")?;
+        for (rank, _) in order.iter().enumerate() {
+            write!(f, "{:>10}", rank + 1)?;
+        }
+        for (rank, &a) in order.iter().enumerate() {
+            let total = self.total(a);
+            let points = total.wins as f64 + total.draws as f64 / 2.0;
+            write!(
+                f,
+                "\n{:<4}{:<24}{:>8.1}{:>10}",
+                rank + 1,
+                self.engines[a],
+                ratings[a],
+                format!("{}/{}", points, total.games())
+            )?;
+            for &b in order.iter() {
+                let s = self.scores[a][b];
+                if a == b || s.games() == 0 {
+                    write!(f, "{:>10}", "-")?;
+                } else {
+                    let points = s.wins as f64 + s.draws as f64 / 2.0;
+                    write!(f, "{:>10}", format!("{}/{}", points, s.games()))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn tournament_test() {
+    use crate::engine_match::random_openings;
+
+    assert_eq!(
+        Schedule::RoundRobin.pairings(3),
+        vec![(0, 1), (0, 2), (1, 2)]
+    );
+    assert_eq!(Schedule::Gauntlet.pairings(3), vec![(0, 1), (0, 2)]);
+
+    let tournament = Tournament {
+        engines: vec![
+            "greedy".to_string(),
+            "random:1".to_string(),
+            "random:2".to_string(),
+        ],
+        openings: random_openings(1, 4, 1),
+        config: TournamentConfig {
+            games_per_pair: 2,
+            limits: SearchLimits::depth(1),
+            max_plies: 40,
+            concurrency: 2,
+            ..TournamentConfig::default()
+        },
+    };
+    assert_eq!(tournament.schedule().len(), 6);
+
+    let path = std::env::temp_dir().join(format!("tournament_test_{}.txt", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let table = tournament.run(&path, |_, _| {}).unwrap();
+    assert_eq!((0..3).map(|e| table.total(e).games()).sum::<u32>(), 12);
+
+    // a resumed tournament only plays the missing games
+    let records = read_records(&path).unwrap();
+    let lines: Vec<String> = records.iter().skip(2).map(|r| r.to_line()).collect();
+    std::fs::write(&path, lines.join("\n") + "\n").unwrap();
+    assert_eq!(tournament.pending(&read_records(&path).unwrap()).len(), 2);
+    let mut new_games = 0;
+    let table = tournament.run(&path, |_, _| new_games += 1).unwrap();
+    assert_eq!(new_games, 2);
+    assert_eq!(read_records(&path).unwrap().len(), 6);
+    std::fs::remove_file(&path).unwrap();
+
+    let ratings = table.ratings();
+    assert!(ratings.iter().sum::<f64>().abs() < 1e-6);
+    assert!(table.to_string().contains("greedy"));
+
+    // a clearly stronger engine gets the higher rating
+    let mut table = Crosstable::new(vec!["a".to_string(), "b".to_string()]);
+    table.scores[0][1] = Score {
+        wins: 8,
+        draws: 0,
+        losses: 2,
+    };
+    table.scores[1][0] = Score {
+        wins: 2,
+        draws: 0,
+        losses: 8,
+    };
+    let ratings = table.ratings();
+    assert!(ratings[0] > 100.0 && ratings[1] < -100.0);
+}
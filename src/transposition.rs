@@ -2,9 +2,8 @@ use crate::board::Board;
 use crate::cannon_move::BitMove;
 
 pub mod hash {
-    pub static PIECES: [[u64; 64]; 2] = {
+    const fn generate_keys(mut seed: u64) -> [[u64; 64]; 2] {
         let mut res = [[0; 64]; 2];
-        let mut seed = 1070372u64;
         let mut idx = 0;
         let mut c = 0;
         while c < 2 {
@@ -20,7 +19,10 @@ pub mod hash {
         }
 
         res
-    };
+    }
+
+    pub static PIECES: [[u64; 64]; 2] = generate_keys(1070372u64);
+    pub static CASTLES: [[u64; 64]; 2] = generate_keys(3098671u64);
     pub const SIDE: u64 = 13442441245975073873;
 }
 
@@ -37,8 +39,11 @@ pub struct TTEntry {
     pub hash: u64,
     pub mv: BitMove,
     score: i16,
+    /// Remaining quiescence depth for entries of the quiescence search
     pub depth: u8,
     pub flag: u8,
+    /// Stored by the quiescence search, whose depths do not compare with the main search
+    pub quiescence: bool,
 }
 
 impl TTEntry {
@@ -96,16 +101,73 @@ impl TTable {
         }
     }
     pub fn insert(&mut self, board: &Board, score: i16, mv: BitMove, flag: u8, depth: u8) {
+        self.store(board, score, mv, flag, depth, false);
+    }
+
+    /// Stores a quiescence result with the quiescence depth that was left. It only replaces
+    /// empty slots, older entries and quiescence entries that were not searched deeper.
+    pub fn insert_quiescence(
+        &mut self,
+        board: &Board,
+        score: i16,
+        mv: BitMove,
+        flag: u8,
+        depth: u8,
+    ) {
+        self.store(board, score, mv, flag, depth, true);
+    }
+
+    fn store(
+        &mut self,
+        board: &Board,
+        score: i16,
+        mv: BitMove,
+        flag: u8,
+        depth: u8,
+        quiescence: bool,
+    ) {
         let age_diff = |current_age: u8, entry_flag: u8| -> u8 {
             ((256 + FLAGS as i32 + current_age as i32 - entry_flag as i32) & AGE_MASK as i32) as u8
         };
         let entry = &mut self.entries[board.hash() as usize & self.index_mask];
-        if entry.depth < depth + age_diff(self.age, entry.flag) {
+        let age = age_diff(self.age, entry.flag);
+        // stored depths are at least 1, so a depth of 0 is an empty slot
+        let replace = if quiescence {
+            age > 0 || entry.depth == 0 || (entry.quiescence && entry.depth <= depth)
+        } else {
+            entry.quiescence || entry.depth < depth + age
+        };
+        if replace {
             entry.hash = board.hash();
             entry.mv = mv;
             entry.score = score;
             entry.depth = depth;
             entry.flag = flag;
+            entry.quiescence = quiescence;
         }
     }
 }
+
+#[test]
+fn replacement_test() {
+    let board = Board::start_position();
+    let mut tt = TTable::default();
+    tt.allocate(1 << 4);
+    let depth_of = |tt: &mut TTable| {
+        let entry = tt.get(board.hash()).unwrap();
+        (entry.depth, entry.quiescence)
+    };
+    tt.insert_quiescence(&board, 1, BitMove::null(), FLAG_EXACT, 3);
+    assert_eq!(depth_of(&mut tt), (3, true));
+    // a shallower quiescence result keeps the deeper one
+    tt.insert_quiescence(&board, 2, BitMove::null(), FLAG_EXACT, 2);
+    assert_eq!(depth_of(&mut tt), (3, true));
+    // main search entries replace quiescence entries, but not the other way round
+    tt.insert(&board, 3, BitMove::null(), FLAG_EXACT, 2);
+    assert_eq!(depth_of(&mut tt), (2, false));
+    tt.insert_quiescence(&board, 4, BitMove::null(), FLAG_EXACT, 14);
+    assert_eq!(depth_of(&mut tt), (2, false));
+    // a main search entry of the same depth is kept
+    tt.insert(&board, 5, BitMove::null(), FLAG_EXACT, 2);
+    assert_eq!(tt.get(board.hash()).unwrap().score(), 3);
+}
@@ -0,0 +1,180 @@
+use crate::board::Board;
+use crate::color::Color;
+use crate::eval::{eval_with, win_probability, EvalParams};
+use crate::params::Params;
+use crate::record::GameRecord;
+use rayon::prelude::*;
+
+/// A quiet position from a finished game with the score white got in that game.
+pub struct TuningPosition {
+    pub board: Board,
+    pub result: f64,
+}
+
+/// Collects the quiet positions of all games, skipping the first `skip_plies` of every game.
+/// A position is quiet if there are no captures or shots and no town is threatened,
+/// so the static eval is a fair estimate of it.
+pub fn extract_positions(records: &[GameRecord], skip_plies: usize) -> Vec<TuningPosition> {
+    records
+        .par_iter()
+        .flat_map_iter(|record| {
+            record
+                .positions()
+                .into_iter()
+                .skip(skip_plies)
+                .filter(|(board, _)| {
+                    !board.last_capture()
+                        && board.generate_captures().is_empty()
+                        && !board.town_threatened(Color::White)
+                        && !board.town_threatened(Color::Black)
+                })
+                .map(|(board, _)| TuningPosition {
+                    // a fresh board does not keep the whole game history alive
+                    board: Board::from_fen(&board.to_fen()).unwrap(),
+                    result: record.result.score_for(Color::White),
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// Fits `EvalParams` to game results by minimising the mean squared error between
+/// the game result and the win probability predicted from the eval.
+pub struct Tuner {
+    pub positions: Vec<TuningPosition>,
+    /// Scales eval scores before mapping them to a win probability
+    pub k: f64,
+}
+
+impl Tuner {
+    pub fn new(positions: Vec<TuningPosition>) -> Self {
+        Self { positions, k: 1.0 }
+    }
+
+    pub fn error(&self, params: &EvalParams) -> f64 {
+        self.error_with_k(params, self.k)
+    }
+
+    fn error_with_k(&self, params: &EvalParams, k: f64) -> f64 {
+        if self.positions.is_empty() {
+            return 0.0;
+        }
+        let sum: f64 = self
+            .positions
+            .par_iter()
+            .map(|p| {
+                let score = eval_with(&p.board, params) as f64;
+                let score = match p.board.side_to_move() {
+                    Color::White => score,
+                    Color::Black => -score,
+                };
+                (p.result - win_probability(score, k)).powi(2)
+            })
+            .sum();
+        sum / self.positions.len() as f64
+    }
+
+    /// Finds the scaling constant that fits the current parameters best,
+    /// so tuning does not just rescale all weights.
+    pub fn fit_k(&mut self, params: &EvalParams) -> f64 {
+        let mut best = self.k;
+        let mut step = 1.0;
+        for _ in 0..5 {
+            let start = (best - step * 10.0).max(step / 10.0);
+            best = (0..=20)
+                .map(|i| start + i as f64 * step)
+                .map(|k| (k, self.error_with_k(params, k)))
+                .min_by(|a, b| a.1.total_cmp(&b.1))
+                .map(|(k, _)| k)
+                .unwrap_or(best);
+            step /= 10.0;
+        }
+        self.k = best;
+        best
+    }
+
+    /// Local search over all parameters: every parameter is moved by one in both directions
+    /// and changes are kept as long as they lower the error.
+    /// `report` is called after every iteration with the iteration, error and parameters.
+    pub fn tune<F: FnMut(usize, f64, &EvalParams)>(
+        &self,
+        start: &EvalParams,
+        max_iterations: usize,
+        mut report: F,
+    ) -> EvalParams {
+        let mut best = start.clone();
+        let mut best_error = self.error(&best);
+
+        for iteration in 0..max_iterations {
+            let mut improved = false;
+            for name in EvalParams::names() {
+                let value = best.get(name).unwrap();
+                for delta in [1, -1] {
+                    let mut candidate = best.clone();
+                    if candidate.set(name, &(value + delta).to_string()).is_err() {
+                        continue;
+                    }
+                    let error = self.error(&candidate);
+                    if error < best_error {
+                        best = candidate;
+                        best_error = error;
+                        improved = true;
+                        break;
+                    }
+                }
+            }
+            report(iteration, best_error, &best);
+            if !improved {
+                break;
+            }
+        }
+        best
+    }
+}
+
+#[test]
+fn tuner_test() {
+    use crate::notation::parse_move;
+    use crate::record::GameResult;
+
+    let mut board = Board::start_position();
+    let start = board.shallow_clone();
+    let mut moves = Vec::new();
+    for m in ["a3a4", "h6h5", "c3c4", "f6f5"] {
+        let m = parse_move(&board, m).unwrap();
+        board.apply_move(m);
+        moves.push(m);
+    }
+    let records = vec![GameRecord::new(&start, moves, GameResult::WhiteWin)];
+    let positions = extract_positions(&records, 1);
+    assert!(!positions.is_empty() && positions.len() <= 3);
+    assert!(positions.iter().all(|p| p.result == 1.0));
+
+    let mut tuner = Tuner::new(positions);
+    let params = EvalParams::default();
+    tuner.fit_k(&params);
+    let tuned = tuner.tune(&params, 3, |_, _, _| {});
+    assert!(tuner.error(&tuned) <= tuner.error(&params));
+
+    // the side with more soldiers always won, so soldiers without value must gain some
+    let positions = [
+        ("B7/8/3b4/8/w1w1w3/8/8/7W w", 1.0),
+        ("B7/8/b1b1b3/8/3w4/8/8/7W w", 0.0),
+        ("B7/8/3b4/8/w1w1w3/8/8/7W b", 1.0),
+        ("B7/8/b1b1b3/8/3w4/8/8/7W b", 0.0),
+    ]
+    .iter()
+    .map(|&(fen, result)| TuningPosition {
+        board: Board::from_fen(fen).unwrap(),
+        result,
+    })
+    .collect();
+    let tuner = Tuner::new(positions);
+    let params = EvalParams {
+        piece_value: 0,
+        ..EvalParams::default()
+    };
+    let tuned = tuner.tune(&params, 3, |_, _, _| {});
+    assert!(tuned.piece_value > 0);
+    assert!(tuner.error(&tuned) < tuner.error(&params));
+}